@@ -0,0 +1,52 @@
+// Compares mining with the fixed 2345 chunks mine_for_proof used to split its range into
+// against ChunkSchedule::Adaptive. Run with `cargo bench`; optional arguments are the
+// number of workers and blocks per difficulty.
+use a3::block::{Block, ChunkSchedule, MiningOptions};
+use std::time::{Duration, Instant};
+
+const FIXED_CHUNKS: u64 = 2345;
+const DIFFICULTIES: [u8; 4] = [6, 12, 16, 20];
+
+fn mine_blocks(difficulty: u8, blocks: usize, workers: usize, schedule: ChunkSchedule) -> (Duration, u64) {
+    // mine a short chain, returning the time taken and the sum of the proofs found
+    let mut previous = Block::initial(difficulty);
+    let mut proofs = 0;
+    let started = Instant::now();
+    for i in 0..blocks {
+        let mut block = if i == 0 { previous.clone() } else { Block::next(&previous, format!("block {}", i)) };
+        // the range mine_for_proof searched before it moved on to later windows
+        let end = 8u64 << difficulty;
+        let proof = block
+            .mine_range_with(workers, 0, end, schedule, MiningOptions::default())
+            .expect("no proof in the first window");
+        block.set_proof(proof);
+        proofs += proof;
+        previous = block;
+    }
+    (started.elapsed(), proofs)
+}
+
+fn main() {
+    // `cargo bench` passes "--bench"; skip flags
+    let args: Vec<usize> = std::env::args().skip(1).filter_map(|a| a.parse().ok()).collect();
+    let workers = args.first().copied().unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()));
+    let blocks = args.get(1).copied().unwrap_or(8);
+
+    println!("{} workers, {} blocks per difficulty", workers, blocks);
+    println!("{:>10} {:>14} {:>14} {:>8}", "difficulty", "fixed", "adaptive", "speedup");
+    for difficulty in DIFFICULTIES {
+        let (fixed, fixed_proofs) = mine_blocks(difficulty, blocks, workers, ChunkSchedule::Fixed(FIXED_CHUNKS));
+        let (adaptive, adaptive_proofs) = mine_blocks(difficulty, blocks, workers, ChunkSchedule::Adaptive);
+        // with one worker both find the lowest proof of each block, so the same chain
+        if workers == 1 {
+            assert_eq!(fixed_proofs, adaptive_proofs);
+        }
+        println!(
+            "{:>10} {:>14?} {:>14?} {:>7.2}x",
+            difficulty,
+            fixed,
+            adaptive,
+            fixed.as_secs_f64() / adaptive.as_secs_f64()
+        );
+    }
+}
@@ -1,33 +1,209 @@
-use crate::queue::{Task, WorkQueue};
+use crate::encoding::{DecodeError, Encoding, Reader, ENCODING_VERSION};
+use crate::hasher::{AnyHasher, BlockHasher, HashFunction};
+use crate::pow::{AnyPowRule, BelowTarget, Difficulty, PowRule, PowRuleKind, Target};
+use crate::queue::{Task, TaskPanic, WorkQueue};
 use digest::consts::U32;
 use sha2::digest::generic_array::GenericArray;
-use sha2::{Digest, Sha256};
-use std::fmt::Write;
+use std::fmt::{self, Write};
 use std::sync;
+use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-type Hash = GenericArray<u8, U32>;
+pub type Hash = GenericArray<u8, U32>;
 
-#[derive(Debug, Clone)]
+// largest `data` payload a valid block may carry, in bytes
+pub const MAX_DATA_LEN: usize = 1 << 20;
+
+// a MiningTask checks for cancellation once every this many proofs
+const CANCEL_CHECK_INTERVAL: u64 = 1 << 10;
+
+// with ChunkSchedule::Adaptive, each chunk should take a worker about this long to check...
+const ADAPTIVE_CHUNK_TIME: Duration = Duration::from_millis(20);
+// ...but is never shorter than this many proofs, so queue overhead stays small
+const MIN_ADAPTIVE_CHUNK: u64 = CANCEL_CHECK_INTERVAL;
+
+// flags byte of the binary encoding: which optional fields follow, and how the block is hashed
+const FLAG_TARGET: u8 = 0x01;
+const FLAG_TIMESTAMP: u8 = 0x02;
+const FLAG_LEGACY: u8 = 0x04; // hashed with the legacy string, not these bytes
+const FLAG_PROOF: u8 = 0x08;
+const ALL_FLAGS: u8 = FLAG_TARGET | FLAG_TIMESTAMP | FLAG_LEGACY | FLAG_PROOF;
+
+// default time between progress reports while mining
+const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    MissingProof,
+    InsufficientWork { difficulty: Difficulty, proof: u64 },
+    BadParentLink { expected: Hash, found: Hash },
+    BadGeneration { expected: u64, found: u64 },
+    DifficultyMismatch { expected: Difficulty, found: Difficulty },
+    HashFunctionMismatch { expected: HashFunction, found: HashFunction },
+    PowRuleMismatch { expected: PowRuleKind, found: PowRuleKind },
+    EncodingMismatch { expected: Encoding, found: Encoding },
+    TargetMismatch { expected: Option<Target>, found: Option<Target> },
+    OversizedData { len: usize, max: usize },
+    MissingTimestamp,
+    UnexpectedTimestamp,
+    TimestampTooOld { timestamp: u64, median_time_past: u64 },
+    TimestampTooFarAhead { timestamp: u64, latest: u64 },
+    BadRetargetWindow { window: u64, max: u64 }, // the chain's Retarget, not a block, is at fault
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::MissingProof => write!(f, "block has not been mined"),
+            ValidationError::InsufficientWork { difficulty, proof } => {
+                write!(f, "proof {} does not meet difficulty {}", proof, difficulty)
+            }
+            ValidationError::BadParentLink { expected, found } => {
+                write!(f, "previous hash is {:02x}, expected {:02x}", found, expected)
+            }
+            ValidationError::BadGeneration { expected, found } => {
+                write!(f, "generation is {}, expected {}", found, expected)
+            }
+            ValidationError::DifficultyMismatch { expected, found } => {
+                write!(f, "difficulty is {}, expected {}", found, expected)
+            }
+            ValidationError::HashFunctionMismatch { expected, found } => {
+                write!(f, "hash function is {}, expected {}", found, expected)
+            }
+            ValidationError::PowRuleMismatch { expected, found } => {
+                write!(f, "proof-of-work rule is {}, expected {}", found, expected)
+            }
+            ValidationError::EncodingMismatch { expected, found } => {
+                write!(f, "block encoding is {}, expected {}", found, expected)
+            }
+            ValidationError::TargetMismatch { expected, found } => {
+                let show = |t: &Option<Target>| t.map_or(String::from("none"), |t| t.to_string());
+                write!(f, "target is {}, expected {}", show(found), show(expected))
+            }
+            ValidationError::OversizedData { len, max } => {
+                write!(f, "data is {} bytes, at most {} allowed", len, max)
+            }
+            ValidationError::MissingTimestamp => write!(f, "block has no timestamp"),
+            ValidationError::UnexpectedTimestamp => write!(f, "block has a timestamp, but the chain doesn't use them"),
+            ValidationError::TimestampTooOld { timestamp, median_time_past } => {
+                write!(f, "timestamp {} is not after the median time past {}", timestamp, median_time_past)
+            }
+            ValidationError::TimestampTooFarAhead { timestamp, latest } => {
+                write!(f, "timestamp {} is in the future, the latest allowed is {}", timestamp, latest)
+            }
+            ValidationError::BadRetargetWindow { window, max } => {
+                write!(f, "retarget window is {} blocks, expected 1 to {}", window, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MiningError {
+    NotFound, // every proof in the range was checked and none was valid
+    Cancelled,
+    TimedOut(MiningProgress), // how far mining got before the deadline
+    Panicked(TaskPanic),      // checking a chunk panicked, so part of the range went unsearched
+    NoWorkers,                // mining needs at least one worker
+    Disconnected,             // the workers went away before every chunk was checked
+}
+
+impl fmt::Display for MiningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MiningError::NotFound => write!(f, "no valid proof in the searched range"),
+            MiningError::Cancelled => write!(f, "mining was cancelled"),
+            MiningError::TimedOut(progress) => {
+                write!(f, "mining timed out after checking {} proofs in {:?}", progress.checked, progress.elapsed)
+            }
+            MiningError::Panicked(panic) => write!(f, "mining failed: {}", panic),
+            MiningError::NoWorkers => write!(f, "mining needs at least one worker"),
+            MiningError::Disconnected => write!(f, "mining failed: the workers went away"),
+        }
+    }
+}
+
+impl std::error::Error for MiningError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MiningProgress {
+    pub checked: u64, // proofs checked so far (approximate while mining is running)
+    pub elapsed: Duration,
+}
+
+impl MiningProgress {
+    pub fn hash_rate(&self) -> f64 {
+        // proofs checked per second
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 { self.checked as f64 / secs } else { 0.0 }
+    }
+}
+
+// Optional behaviour for Block::mine_range_with. The default behaves exactly like mine_range.
+pub struct MiningOptions<'a> {
+    pub cancel: CancelToken,                                  // give up with MiningError::Cancelled once cancelled
+    pub lowest: bool,                                         // return the lowest valid proof rather than the first found
+    pub deadline: Option<Instant>,                            // give up with MiningError::TimedOut at this time
+    pub progress: Option<&'a mut dyn FnMut(&MiningProgress)>, // called periodically, and once when mining ends
+    pub progress_interval: Duration,
+}
+
+impl Default for MiningOptions<'_> {
+    fn default() -> Self {
+        MiningOptions {
+            cancel: CancelToken::new(),
+            lowest: false,
+            deadline: None,
+            progress: None,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Block {
     prev_hash: Hash,
     generation: u64,
-    difficulty: u8,
+    difficulty: Difficulty,
     data: String,
     proof: Option<u64>,
+    hash_function: HashFunction,
+    pow_rule: PowRuleKind,
+    target: Option<Target>, // if set, replaces `difficulty` for the Target rule
+    timestamp: Option<u64>, // seconds since the Unix epoch, for chains that use timestamps
+    extra_nonce: u64,       // moves to a fresh set of proofs once every u64 proof has failed
+    encoding: Encoding,
 }
 
 impl Block {
-    pub fn initial(difficulty: u8) -> Block {
+    pub fn initial(difficulty: impl Into<Difficulty>) -> Block {
         // create and return a new initial block
         Block {
             prev_hash: Hash::default(),
             generation: 0,
-            difficulty: difficulty,
+            difficulty: difficulty.into(),
             data: "".to_string(),
-            proof: None
+            proof: None,
+            hash_function: HashFunction::default(),
+            pow_rule: PowRuleKind::default(),
+            target: None,
+            timestamp: None,
+            extra_nonce: 0,
+            encoding: Encoding::default(),
         }
     }
 
+    pub fn initial_legacy(difficulty: impl Into<Difficulty>) -> Block {
+        // an initial block hashed with the legacy string, as blocks were before the binary encoding
+        let mut block = Block::initial(difficulty);
+        block.set_encoding(Encoding::Legacy);
+        block
+    }
+
+    #[allow(clippy::redundant_field_names)]
     pub fn next(previous: &Block, data: String) -> Block {
         // create and return a block that could follow `previous` in the chain
         Block {
@@ -35,15 +211,108 @@ impl Block {
             generation: previous.generation + 1,
             difficulty: previous.difficulty,
             data: data,
-            proof: None
+            proof: None,
+            hash_function: previous.hash_function,
+            pow_rule: previous.pow_rule,
+            target: previous.target,
+            timestamp: None,
+            extra_nonce: 0,
+            encoding: previous.encoding,
+        }
+    }
+
+    pub(crate) fn from_link(prev_hash: Hash, generation: u64, data: String) -> Block {
+        // a block claiming to follow the block hashing to `prev_hash`, otherwise like Block::initial(0)
+        Block { prev_hash, generation, data, ..Block::initial(0) }
+    }
+
+    pub fn prev_hash(&self) -> &Hash {
+        &self.prev_hash
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    pub fn proof(&self) -> Option<u64> {
+        self.proof
+    }
+
+    pub fn hash_function(&self) -> HashFunction {
+        self.hash_function
+    }
+
+    pub fn pow_rule_kind(&self) -> PowRuleKind {
+        self.pow_rule
+    }
+
+    pub fn pow_rule(&self) -> AnyPowRule {
+        // the rule this block's hash has to satisfy, at this block's difficulty or target
+        match (self.pow_rule, self.target) {
+            (PowRuleKind::Target, Some(target)) => AnyPowRule::Target(BelowTarget(target)),
+            (kind, _) => kind.rule(self.difficulty),
+        }
+    }
+
+    pub fn target(&self) -> Option<Target> {
+        self.target
+    }
+
+    pub fn work_target(&self) -> Target {
+        // the target needing the same expected work as this block's proof, whichever rule it uses
+        match (self.pow_rule, self.target) {
+            (PowRuleKind::Target, Some(target)) => target,
+            _ => Target::from_difficulty(self.difficulty),
         }
     }
 
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    pub fn extra_nonce(&self) -> u64 {
+        self.extra_nonce
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
     pub fn hash_string_for_proof(&self, proof: u64) -> String {
         // return the hash string this block would have if we set the proof to `proof`.
-        let mut prev_hash_string = String::new();
-        write!(&mut prev_hash_string, "{:02x}", self.prev_hash).unwrap();
-        format!("{}:{}:{}:{}:{}", prev_hash_string, self.generation, self.difficulty, self.data, proof)
+        // With the binary encoding, that's the hashed bytes in hex.
+        match self.encoding {
+            Encoding::Legacy => self.legacy_string_for_proof(proof),
+            Encoding::Binary => self.preimage_for_proof(proof).iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+
+    fn legacy_string_for_proof(&self, proof: u64) -> String {
+        // The target, timestamp and extra nonce only appear when set, so older blocks hash as they always did.
+        // The extra nonce goes straight after the prev hash, which is always 64 hex digits: whether a '+'
+        // or a ':' comes next can't be faked by `data`, unlike a field after it.
+        let mut hash_string = String::new();
+        write!(&mut hash_string, "{:02x}", self.prev_hash).unwrap();
+        if self.extra_nonce != 0 {
+            write!(&mut hash_string, "+{}", self.extra_nonce).unwrap();
+        }
+        write!(&mut hash_string, ":{}:{}:", self.generation, self.difficulty).unwrap();
+        if let Some(target) = self.target {
+            write!(&mut hash_string, "{}:", target.to_compact()).unwrap();
+        }
+        if let Some(timestamp) = self.timestamp {
+            write!(&mut hash_string, "{}:", timestamp).unwrap();
+        }
+        write!(&mut hash_string, "{}:{}", self.data, proof).unwrap();
+        hash_string
     }
 
     pub fn hash_string(&self) -> String {
@@ -52,13 +321,150 @@ impl Block {
         self.hash_string_for_proof(p)
     }
 
+    pub fn preimage_for_proof(&self, proof: u64) -> Vec<u8> {
+        // the bytes hashed for `proof`, in the block's encoding
+        match self.encoding {
+            Encoding::Legacy => self.legacy_string_for_proof(proof).into_bytes(),
+            Encoding::Binary => {
+                let mut bytes = Vec::new();
+                self.write_header(true, &mut |b| bytes.extend_from_slice(b));
+                bytes.extend_from_slice(&proof.to_be_bytes());
+                bytes
+            }
+        }
+    }
+
+    fn write_header(&self, with_proof: bool, out: &mut impl FnMut(&[u8])) {
+        // The binary encoding, up to (not including) the proof: fixed-width big-endian
+        // fields, with `data` length-prefixed and optional fields marked in the flags byte.
+        let mut flags = 0;
+        if self.target.is_some() {
+            flags |= FLAG_TARGET;
+        }
+        if self.timestamp.is_some() {
+            flags |= FLAG_TIMESTAMP;
+        }
+        if self.encoding == Encoding::Legacy {
+            flags |= FLAG_LEGACY;
+        }
+        if with_proof {
+            flags |= FLAG_PROOF;
+        }
+        out(&[ENCODING_VERSION, flags]);
+        out(&self.prev_hash);
+        out(&self.generation.to_be_bytes());
+        out(&self.difficulty.bits().to_be_bytes());
+        out(&[self.hash_function.id(), self.pow_rule.id()]);
+        if let Some(target) = self.target {
+            out(&target.to_compact().to_be_bytes());
+        }
+        if let Some(timestamp) = self.timestamp {
+            out(&timestamp.to_be_bytes());
+        }
+        out(&self.extra_nonce.to_be_bytes());
+        out(&(self.data.len() as u64).to_be_bytes());
+        out(self.data.as_bytes());
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // The block in the binary encoding, for storage. Whichever encoding the block is
+        // hashed with, this holds everything needed to rebuild it.
+        let mut bytes = Vec::new();
+        self.write_header(self.proof.is_some(), &mut |b| bytes.extend_from_slice(b));
+        if let Some(proof) = self.proof {
+            bytes.extend_from_slice(&proof.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Block, DecodeError> {
+        // Decode a block from to_bytes. Only the encoding is checked, not the proof of work.
+        let mut reader = Reader::new(bytes);
+        let version = reader.u8()?;
+        if version != ENCODING_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let flags = reader.u8()?;
+        if flags & !ALL_FLAGS != 0 {
+            return Err(DecodeError::UnknownFlags(flags));
+        }
+        let prev_hash = Hash::from(reader.array::<32>()?);
+        let generation = reader.u64()?;
+        let difficulty = Difficulty::new(reader.u16()?).map_err(DecodeError::BadDifficulty)?;
+        let id = reader.u8()?;
+        let hash_function = HashFunction::from_id(id).ok_or(DecodeError::UnknownHashFunction(id))?;
+        let id = reader.u8()?;
+        let pow_rule = PowRuleKind::from_id(id).ok_or(DecodeError::UnknownPowRule(id))?;
+        let target = if flags & FLAG_TARGET != 0 {
+            let bits = reader.u32()?;
+            // one encoding per target, so re-encoding gives back the same bytes
+            let target = Target::from_compact(bits).filter(|t| t.to_compact() == bits);
+            Some(target.ok_or(DecodeError::BadTarget(bits))?)
+        } else {
+            None
+        };
+        let timestamp = if flags & FLAG_TIMESTAMP != 0 { Some(reader.u64()?) } else { None };
+        let extra_nonce = reader.u64()?;
+        let len = usize::try_from(reader.u64()?).map_err(|_| DecodeError::Truncated)?;
+        let data = String::from_utf8(reader.take(len)?.to_vec()).map_err(|_| DecodeError::InvalidData)?;
+        let proof = if flags & FLAG_PROOF != 0 { Some(reader.u64()?) } else { None };
+        reader.finish()?;
+        Ok(Block {
+            prev_hash,
+            generation,
+            difficulty,
+            data,
+            proof,
+            hash_function,
+            pow_rule,
+            target,
+            timestamp,
+            extra_nonce,
+            encoding: if flags & FLAG_LEGACY != 0 { Encoding::Legacy } else { Encoding::Binary },
+        })
+    }
+
+    fn hash_prefix<H: BlockHasher>(&self, hasher: &mut H) {
+        // Feed the preimage up to (not including) the proof into `hasher`, byte-for-byte
+        // the same as preimage_for_proof, but using stack buffers instead of Strings.
+        if self.encoding == Encoding::Binary {
+            self.write_header(true, &mut |b| hasher.write(b));
+            return;
+        }
+        let mut hex = [0u8; 64];
+        let mut digits = [0u8; 20];
+        hasher.write(write_hex(&mut hex, &self.prev_hash));
+        if self.extra_nonce != 0 {
+            hasher.write(b"+");
+            hasher.write(write_decimal(&mut digits, self.extra_nonce));
+        }
+        hasher.write(b":");
+        hasher.write(write_decimal(&mut digits, self.generation));
+        hasher.write(b":");
+        hasher.write(write_decimal(&mut digits, self.difficulty.bits().into()));
+        hasher.write(b":");
+        if let Some(target) = self.target {
+            hasher.write(write_decimal(&mut digits, target.to_compact().into()));
+            hasher.write(b":");
+        }
+        if let Some(timestamp) = self.timestamp {
+            hasher.write(write_decimal(&mut digits, timestamp));
+            hasher.write(b":");
+        }
+        hasher.write(self.data.as_bytes());
+        hasher.write(b":");
+    }
+
     pub fn hash_for_proof(&self, proof: u64) -> Hash {
         // return the block's hash as it would be if we set the proof to `proof`.
-        let mut hasher = Sha256::new();
-        let hash_string = self.hash_string_for_proof(proof);
-        hasher.update(hash_string);
-        let result = hasher.finalize();
-        return result;
+        self.hash_for_proof_with(self.hash_function.hasher(), proof)
+    }
+
+    pub fn hash_for_proof_with<H: BlockHasher>(&self, mut hasher: H, proof: u64) -> Hash {
+        // hash with `hasher` instead of the block's configured hash function
+        self.hash_prefix(&mut hasher);
+        write_proof(&mut hasher, self.encoding, proof);
+        hasher.finish()
     }
 
     pub fn hash(&self) -> Hash {
@@ -71,33 +477,122 @@ impl Block {
         self.proof = Some(proof);
     }
 
+    // changing the difficulty invalidates any proof already found
+    pub fn set_difficulty(self: &mut Block, difficulty: impl Into<Difficulty>) {
+        self.difficulty = difficulty.into();
+        self.proof = None;
+    }
+
+    // so does changing the hash function or proof-of-work rule; blocks created by Block::next inherit both
+    pub fn set_hash_function(self: &mut Block, hash_function: HashFunction) {
+        self.hash_function = hash_function;
+        self.proof = None;
+    }
+
+    pub fn set_pow_rule(self: &mut Block, pow_rule: PowRuleKind) {
+        self.pow_rule = pow_rule;
+        self.proof = None;
+    }
+
+    pub fn set_timestamp(self: &mut Block, timestamp: u64) {
+        self.timestamp = Some(timestamp);
+        self.proof = None;
+    }
+
+    pub fn set_encoding(self: &mut Block, encoding: Encoding) {
+        self.encoding = encoding;
+        self.proof = None;
+    }
+
+    pub fn set_extra_nonce(self: &mut Block, extra_nonce: u64) {
+        self.extra_nonce = extra_nonce;
+        self.proof = None;
+    }
+
+    pub fn set_target(self: &mut Block, target: Target) {
+        // Switches the block to the Target rule. The target is rounded down to what its compact
+        // encoding (which is what gets hashed) can represent.
+        let compact = target.to_compact();
+        self.target = Target::from_compact(compact);
+        self.pow_rule = PowRuleKind::Target;
+        self.proof = None;
+    }
+
     pub fn is_valid_for_proof(&self, proof: u64) -> bool {
         // would this block be valid if we set the proof to `proof`?
-        let hash: Hash = self.hash_for_proof(proof);
-        let n_bytes: usize = (self.difficulty/8).into();
-        let n_bits: usize = (self.difficulty%8).into();
-
-        let last_byte_index = hash.len() - 1;
-        if n_bytes > 0 {
-            for i in 0..n_bytes {
-                if hash[last_byte_index - i] != 0u8{
-                    return false;
-                }
-            }
+        self.pow_rule().accepts(&self.hash_for_proof(proof))
+    }
+
+    pub fn proof_hasher(&self) -> ProofHasher {
+        self.proof_hasher_with(self.hash_function.hasher())
+    }
+
+    pub fn proof_hasher_with<H: BlockHasher>(&self, mut hasher: H) -> ProofHasher<H> {
+        // Everything before the proof in the hash string is the same for every proof, so
+        // hash it once here. ProofHasher then only has to hash the proof's digits.
+        self.hash_prefix(&mut hasher);
+        ProofHasher { prefix: hasher, encoding: self.encoding }
+    }
+
+    pub fn validate_for_proof(&self, proof: u64) -> Result<(), ValidationError> {
+        // like is_valid_for_proof, but reports why the block would be rejected
+        if self.data.len() > MAX_DATA_LEN {
+            return Err(ValidationError::OversizedData { len: self.data.len(), max: MAX_DATA_LEN });
+        }
+        if !self.is_valid_for_proof(proof) {
+            return Err(ValidationError::InsufficientWork { difficulty: self.difficulty, proof });
         }
+        Ok(())
+    }
 
-        let next_byte_from_end = hash.len() - 1 - n_bytes;
-        if hash[next_byte_from_end] as usize % (1<<n_bits) != 0 {
-            return false;
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        match self.proof {
+            Some(p) => self.validate_for_proof(p),
+            None => Err(ValidationError::MissingProof),
         }
-        return true;
     }
 
-    pub fn is_valid(&self) -> bool {
-        if self.proof.is_none() {
-            return false;
+    pub fn validate_next(&self, previous: &Block) -> Result<(), ValidationError> {
+        // check that this block is mined and correctly follows `previous` (which must be mined) in a chain
+        self.validate_next_with(previous, previous.difficulty, previous.target)
+    }
+
+    pub fn validate_next_with(
+        &self,
+        previous: &Block,
+        difficulty: Difficulty,
+        target: Option<Target>,
+    ) -> Result<(), ValidationError> {
+        // like validate_next, for chains that decide each block's difficulty and target themselves
+        if self.prev_hash != previous.hash() {
+            return Err(ValidationError::BadParentLink { expected: previous.hash(), found: self.prev_hash });
+        }
+        if self.generation != previous.generation + 1 {
+            return Err(ValidationError::BadGeneration { expected: previous.generation + 1, found: self.generation });
+        }
+        if self.difficulty != difficulty {
+            return Err(ValidationError::DifficultyMismatch { expected: difficulty, found: self.difficulty });
+        }
+        if self.hash_function != previous.hash_function {
+            return Err(ValidationError::HashFunctionMismatch {
+                expected: previous.hash_function,
+                found: self.hash_function,
+            });
         }
-        self.is_valid_for_proof(self.proof.unwrap())
+        if self.pow_rule != previous.pow_rule {
+            return Err(ValidationError::PowRuleMismatch { expected: previous.pow_rule, found: self.pow_rule });
+        }
+        if self.encoding != previous.encoding {
+            return Err(ValidationError::EncodingMismatch { expected: previous.encoding, found: self.encoding });
+        }
+        if self.target != target {
+            return Err(ValidationError::TargetMismatch { expected: target, found: self.target });
+        }
+        self.validate()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.validate().is_ok()
     }
 
     // Mine in a very simple way: check sequentially until a valid hash is found.
@@ -114,60 +609,87 @@ impl Block {
     //public function for testing correctness of MiningTask
     pub fn mine_serial_using_task(self: &mut Block){
         let shared_block = sync::Arc::new(self.clone());
-        let mining_task = MiningTask::new(shared_block.clone(), 0, 8 * (1<<self.difficulty));
-        match mining_task.run() {
-            Some(p) => {
-                self.set_proof(p);
-            }
-            None => {
-                //No proof found
-            }
+        let search = sync::Arc::new(SearchState::new(CancelToken::new()));
+        let mining_task = MiningTask::new(shared_block.clone(), 0, self.search_range_end(), search);
+        if let Some(ChunkOutcome::Found(p)) = mining_task.run() {
+            self.set_proof(p);
         }
     }
 
     pub fn mine_serial_parallel(self: &Block, start: u64, end: u64)-> Option<u64>{
-        let mut p = start;
-        while p <= end {
-            if self.is_valid_for_proof(p){
-                return Some(p);
-            }
-            p += 1;
-        }
-        return None;
+        let hasher = self.proof_hasher();
+        let rule = self.pow_rule();
+        (start..=end).find(|&p| rule.accepts(&hasher.hash_for_proof(p)))
     }
 
     //deprecated test function using serial mining to ensure mine_range logic is correct before further implementation
-    pub fn mine_range_serial(self: &Block, _workers: usize, start: u64, end: u64, chunks: u64) -> u64 {
+    pub fn mine_range_serial(self: &Block, _workers: usize, start: u64, end: u64, chunks: u64) -> Result<u64, MiningError> {
         // With `workers` threads, check proof values in the given range, breaking up
 	    // into `chunks` tasks in a work queue. Return the first valid proof found.
         // HINTS:
         // - Create and use a queue::WorkQueue.
         // - Use sync::Arc to wrap a clone of self for sharing.
-        let num_values_to_check = end - start + 1;
-        let mut chunk_length = num_values_to_check / chunks;
-        if num_values_to_check % chunks != 0 {
-            chunk_length += 1;
-        }
-
-        for i in 0..chunks {
+        for (parallel_start, parallel_end) in split_range(start, end, chunks) {
             //simulate spawning a thread
-            let parallel_start = chunk_length*i;
-            let parallel_end = parallel_start + chunk_length - 1;
             match self.mine_serial_parallel(parallel_start, parallel_end){
                 Some(p) => {
-                    return p;
+                    return Ok(p);
                 }
                 None => {
                     //Do nothing
                 }
             }
         }
-        return 0;
+        Err(MiningError::NotFound)
+    }
+
+    pub fn mine_range(self: &Block, workers: usize, start: u64, end: u64, chunks: u64) -> Result<u64, MiningError> {
+        self.mine_range_with(workers, start, end, chunks, MiningOptions::default())
+    }
+
+    pub fn mine_range_with_cancel(
+        self: &Block,
+        workers: usize,
+        start: u64,
+        end: u64,
+        chunks: u64,
+        cancel: &CancelToken,
+    ) -> Result<u64, MiningError> {
+        let options = MiningOptions { cancel: cancel.clone(), ..MiningOptions::default() };
+        self.mine_range_with(workers, start, end, chunks, options)
+    }
+
+    pub fn mine_range_lowest(self: &Block, workers: usize, start: u64, end: u64, chunks: u64) -> Result<u64, MiningError> {
+        // Like mine_range, but always returns the smallest valid proof in the range, so the
+        // result doesn't depend on the number of workers or how they were scheduled.
+        let options = MiningOptions { lowest: true, ..MiningOptions::default() };
+        self.mine_range_with(workers, start, end, chunks, options)
+    }
+
+    pub fn mine_range_with(
+        self: &Block,
+        workers: usize,
+        start: u64,
+        end: u64,
+        schedule: impl Into<ChunkSchedule>,
+        options: MiningOptions,
+    ) -> Result<u64, MiningError> {
+        self.mine_range_with_rule(self.pow_rule(), workers, start, end, schedule, options)
     }
 
-    pub fn mine_range(self: &Block, workers: usize, start: u64, end: u64, chunks: u64) -> u64 {
+    pub fn mine_range_with_rule<R: PowRule + 'static>(
+        self: &Block,
+        rule: R,
+        workers: usize,
+        start: u64,
+        end: u64,
+        schedule: impl Into<ChunkSchedule>,
+        mut options: MiningOptions,
+    ) -> Result<u64, MiningError> {
+        // Search for a proof accepted by `rule`, which need not be the block's own rule.
         // With `workers` threads, check proof values in the given range, breaking up
-        // into `chunks` tasks in a work queue. Return the first valid proof found.
+        // into tasks in a work queue as `schedule` says. Return the first valid proof found,
+        // or the lowest one if `options.lowest` is set.
         // HINTS:
         // - Create and use a queue::WorkQueue.
         // - Use sync::Arc to wrap a clone of self for sharing.
@@ -175,79 +697,452 @@ impl Block {
         // check proof values for this block from start to end (inclusive). 
         // The calculation should be done in parallel by the given number of workers and dividing the work into chunks approximately equal parts.
         // Use the work queue. Should be fairly easy to do the work in parallel, and to stop checking proof values after a valid proof is found.
-        let mut q = WorkQueue::<MiningTask>::new(workers);
+        if workers == 0 {
+            // nothing would ever check a chunk
+            return Err(MiningError::NoWorkers);
+        }
+        let started = Instant::now();
+        let mut q = WorkQueue::<MiningTask<R>>::new(workers);
         let shared_block = sync::Arc::new(self.clone());
+        // cancelled by us once we're done, or by the caller through `options.cancel`
+        let search = sync::Arc::new(SearchState::new(options.cancel.child()));
 
-        let num_values_to_check = end - start + 1;
-        let mut chunk_length = num_values_to_check / chunks;
-        //last chunk may be shorter than the rest
-        if num_values_to_check % chunks != 0 {
-            chunk_length += 1;
-        }
+        // the queue knows when every chunk has finished, and so the whole range is exhausted
+        let schedule = schedule.into();
+        let mut chunker = Chunker::new(schedule, start, end);
+        let in_flight_limit = chunker.in_flight_limit(workers) as u64;
+        let mut last_report = started;
+        let mut result = Err(MiningError::NotFound);
+        loop {
+            // chunks are handed out in order, so none after a found proof can hold a lower one
+            while q.pending() < in_flight_limit && result.is_err() {
+                let Some((parallel_start, parallel_end)) = chunker.next_chunk(workers, &search.progress(started)) else {
+                    break;
+                };
+                let _ = q.enqueue(
+                    MiningTask::with_rule (
+                        shared_block.clone(),
+                        rule.clone(),
+                        parallel_start,
+                        parallel_end,
+                        search.clone(),
+                    )
+                );
+            }
+            if q.pending() == 0 {
+                break;
+            }
 
-        for i in 0..chunks {
-            let parallel_start = chunk_length*i;
-            let mut parallel_end = parallel_start + chunk_length - 1;
-            if parallel_end > end {
-                parallel_end = end;
+            let now = Instant::now();
+            if options.deadline.is_some_and(|d| now >= d) {
+                result = Err(MiningError::TimedOut(search.progress(started)));
+                break;
             }
-            let _ = q.enqueue(
-                MiningTask::new (
-                    shared_block.clone(),
-                    parallel_start,
-                    parallel_end,
-                )
-            );
 
-        }
+            // wait for a chunk to finish, waking up in time for the deadline or the next progress report
+            let next_report = options.progress.as_ref().map(|_| last_report + options.progress_interval);
+            // tagged, so a chunk that panicked isn't mistaken for one without a proof
+            let outcome = match options.deadline.into_iter().chain(next_report).min() {
+                // with tasks pending, None means the workers have gone
+                None => q.recv_pending_tagged().ok_or(mpsc::RecvTimeoutError::Disconnected),
+                Some(wake) => q.recv_timeout_tagged(wake.saturating_duration_since(now)),
+            };
 
-        let result = q.recv();
+            if let Some(report) = options.progress.as_mut() {
+                if last_report.elapsed() >= options.progress_interval {
+                    report(&search.progress(started));
+                    last_report = Instant::now();
+                }
+            }
 
+            match outcome.map(|(_, result)| result) {
+                Ok(Ok(ChunkOutcome::Found(p))) => {
+                    // chunks are handed out in order, but may finish out of order
+                    result = Ok(result.map_or(p, |r: u64| r.min(p)));
+                    // Adaptive chunks still in flight finish within about ADAPTIVE_CHUNK_TIME (or at once,
+                    // past `p`), so they are waited for even then, and a lower proof in one of them wins.
+                    // Fixed chunks are all queued up front, and waiting could mean searching most of the range.
+                    if !options.lowest && schedule != ChunkSchedule::Adaptive {
+                        break;
+                    }
+                }
+                Ok(Ok(ChunkOutcome::Cancelled)) => {
+                    result = Err(MiningError::Cancelled);
+                    break;
+                }
+                Ok(Err(panic)) => {
+                    result = Err(MiningError::Panicked(panic));
+                    break;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    // the chunks still pending will never report
+                    result = Err(MiningError::Disconnected);
+                    break;
+                }
+                Ok(Ok(ChunkOutcome::Exhausted)) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+            }
+        }
+
+        // stop the chunks other workers are still checking
+        search.cancel.cancel();
         q.shutdown();
 
-        return result;
+        if let Some(report) = options.progress.as_mut() {
+            report(&search.progress(started));
+        }
+        result
     }
 
-    pub fn mine_for_proof(self: &Block, workers: usize) -> u64 {
-        let range_start: u64 = 0;
-        let range_end: u64 = 8 * (1 << self.difficulty); // 8 * 2^(bits that must be zero)
-        let chunks: u64 = 2345;
-        self.mine_range(workers, range_start, range_end, chunks)
+    pub(crate) fn search_range_end(&self) -> u64 {
+        match self.target {
+            // `as` saturates at u64::MAX
+            Some(target) if self.pow_rule == PowRuleKind::Target => (8.0 * target.expected_work()) as u64,
+            // 8 * 2^(bits that must be zero), about 8 times the expected work, saturating from 61 bits up
+            _ => 1u64.checked_shl(u32::from(self.difficulty.bits()) + 3).unwrap_or(u64::MAX),
+        }
+    }
+
+    pub fn mine_for_proof(self: &Block, workers: usize) -> Result<u64, MiningError> {
+        self.mine_for_proof_with(workers, MiningOptions::default())
+    }
+
+    pub fn mine_for_proof_with(self: &Block, workers: usize, options: MiningOptions) -> Result<u64, MiningError> {
+        // Fails with MiningError::NotFound only if no u64 proof works for this block.
+        self.mine_windows(workers, 0, options)
+    }
+
+    fn mine_windows(self: &Block, workers: usize, first: u64, mut options: MiningOptions) -> Result<u64, MiningError> {
+        // Search windows of search_range_end() + 1 proofs in turn, starting from `first`, until
+        // one holds a proof. Windows are searched in order, so `options.lowest` still finds the
+        // lowest proof. Progress is reported for the whole search rather than per window.
+        let started = Instant::now();
+        let window = self.search_range_end();
+        let mut so_far = MiningProgress { checked: 0, elapsed: Duration::ZERO };
+        let mut start = first;
+        loop {
+            let end = start.saturating_add(window);
+            let checked_before = so_far.checked;
+            let mut caller_progress = options.progress.as_deref_mut();
+            let mut report = |p: &MiningProgress| {
+                so_far = MiningProgress { checked: checked_before + p.checked, elapsed: started.elapsed() };
+                if let Some(report) = caller_progress.as_mut() {
+                    report(&so_far);
+                }
+            };
+            let window_options = MiningOptions {
+                cancel: options.cancel.clone(),
+                lowest: options.lowest,
+                deadline: options.deadline,
+                progress: Some(&mut report),
+                progress_interval: options.progress_interval,
+            };
+            match self.mine_range_with(workers, start, end, ChunkSchedule::Adaptive, window_options) {
+                Err(MiningError::NotFound) if end < u64::MAX => start = end + 1,
+                Err(MiningError::TimedOut(_)) => return Err(MiningError::TimedOut(so_far)),
+                result => return result,
+            }
+        }
+    }
+
+    pub fn mine_with(self: &mut Block, workers: usize, options: MiningOptions) -> Result<(), MiningError> {
+        // Like mine_for_proof_with, but moves on to the next extra nonce whenever every u64
+        // proof has failed, so only cancellation or a deadline can stop it.
+        self.mine_from(workers, 0, options)
+    }
+
+    pub(crate) fn mine_from(self: &mut Block, workers: usize, first: u64, mut options: MiningOptions) -> Result<(), MiningError> {
+        let mut first = first;
+        loop {
+            let window_options = MiningOptions {
+                cancel: options.cancel.clone(),
+                lowest: options.lowest,
+                deadline: options.deadline,
+                progress: match options.progress {
+                    Some(ref mut report) => Some(&mut **report), // reborrowed for this attempt only
+                    None => None,
+                },
+                progress_interval: options.progress_interval,
+            };
+            match self.mine_windows(workers, first, window_options) {
+                Ok(proof) => {
+                    self.proof = Some(proof);
+                    return Ok(());
+                }
+                Err(MiningError::NotFound) => {
+                    let extra_nonce = self.extra_nonce.checked_add(1).expect("every extra nonce has been tried");
+                    self.set_extra_nonce(extra_nonce);
+                    first = 0;
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 
     pub fn mine(self: &mut Block, workers: usize) {
-        self.proof = Some(self.mine_for_proof(workers));
+        self.mine_with(workers, MiningOptions::default()).expect("mining without a cancel token or deadline fails only without workers, or if a chunk panics");
     }
+
+    pub fn mine_lowest(self: &mut Block, workers: usize) {
+        // always finds the same proof as mine_serial, however many workers are used
+        let options = MiningOptions { lowest: true, ..MiningOptions::default() };
+        self.mine_with(workers, options).expect("mining without a cancel token or deadline fails only without workers, or if a chunk panics");
+    }
+}
+
+// Writes `hash` as lowercase hex (like "{:02x}") into `buf`, without allocating.
+fn write_hex<'a>(buf: &'a mut [u8; 64], hash: &Hash) -> &'a [u8] {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    for (i, byte) in hash.iter().enumerate() {
+        buf[2 * i] = DIGITS[(byte >> 4) as usize];
+        buf[2 * i + 1] = DIGITS[(byte & 0xf) as usize];
+    }
+    buf
 }
 
-struct MiningTask {
+// Writes `n` in decimal at the end of `buf` and returns the digits, without allocating.
+fn write_decimal(buf: &mut [u8; 20], mut n: u64) -> &[u8] {
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            return &buf[i..];
+        }
+    }
+}
+
+// A block's hash function with the part of the hash string before the proof already hashed
+// (the "midstate"). Cheap to clone, so each mining worker can have its own.
+#[derive(Debug, Clone)]
+pub struct ProofHasher<H: BlockHasher = AnyHasher> {
+    prefix: H,
+    encoding: Encoding,
+}
+
+impl<H: BlockHasher> ProofHasher<H> {
+    pub fn hash_for_proof(&self, proof: u64) -> Hash {
+        // same result as Block::hash_for_proof
+        let mut hasher = self.prefix.clone();
+        write_proof(&mut hasher, self.encoding, proof);
+        hasher.finish()
+    }
+}
+
+// The proof ends the preimage: in decimal for the legacy string, 8 bytes for the binary encoding.
+fn write_proof<H: BlockHasher>(hasher: &mut H, encoding: Encoding, proof: u64) {
+    match encoding {
+        Encoding::Legacy => {
+            let mut digits = [0u8; 20];
+            hasher.write(write_decimal(&mut digits, proof));
+        }
+        Encoding::Binary => hasher.write(&proof.to_be_bytes()),
+    }
+}
+
+// Split start..=end into at most `chunks` consecutive inclusive ranges of nearly equal length.
+// The last range may be shorter than the rest. Safe for ranges ending at u64::MAX.
+pub(crate) fn split_range(start: u64, end: u64, chunks: u64) -> Vec<(u64, u64)> {
+    if start > end {
+        return Vec::new();
+    }
+    // u128 because start..=end may hold 2^64 values
+    let num_values_to_check = (end - start) as u128 + 1;
+    let chunks = (chunks.max(1) as u128).min(num_values_to_check);
+    let chunk_length = num_values_to_check.div_ceil(chunks);
+    (0..chunks)
+        .map(|i| start as u128 + i * chunk_length)
+        .take_while(|&s| s <= end as u128)
+        .map(|s| (s as u64, (s + chunk_length - 1).min(end as u128) as u64))
+        .collect()
+}
+
+// How Block::mine_range_with splits its range into MiningTasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkSchedule {
+    // split into this many nearly equal chunks, all queued up front
+    Fixed(u64),
+    // Keep two chunks per worker queued, each sized from the hash rate measured so far to take
+    // about ADAPTIVE_CHUNK_TIME, and at most a share of what is left ("guided" scheduling).
+    // Chunks stay short enough to stop soon after a proof is found, and long enough that
+    // queueing them costs little. Since no chunk is handed out once a proof is found, and the
+    // ones in flight are finished, the proof returned is the lowest even without
+    // MiningOptions::lowest.
+    Adaptive,
+}
+
+impl From<u64> for ChunkSchedule {
+    fn from(chunks: u64) -> ChunkSchedule {
+        ChunkSchedule::Fixed(chunks)
+    }
+}
+
+// Hands out the chunks of a range, in order, following a ChunkSchedule.
+pub(crate) struct Chunker {
+    schedule: ChunkSchedule,
+    fixed: std::vec::IntoIter<(u64, u64)>,
+    next: Option<u64>, // first proof not yet handed out, for ChunkSchedule::Adaptive
+    end: u64,
+}
+
+impl Chunker {
+    pub fn new(schedule: ChunkSchedule, start: u64, end: u64) -> Chunker {
+        let fixed = match schedule {
+            ChunkSchedule::Fixed(chunks) => split_range(start, end, chunks),
+            ChunkSchedule::Adaptive => Vec::new(),
+        };
+        Chunker { schedule, fixed: fixed.into_iter(), next: Some(start).filter(|&s| s <= end), end }
+    }
+
+    pub fn in_flight_limit(&self, workers: usize) -> usize {
+        match self.schedule {
+            ChunkSchedule::Fixed(_) => usize::MAX,
+            ChunkSchedule::Adaptive => 2 * workers.max(1),
+        }
+    }
+
+    pub fn next_chunk(&mut self, workers: usize, progress: &MiningProgress) -> Option<(u64, u64)> {
+        match self.schedule {
+            ChunkSchedule::Fixed(_) => self.fixed.next(),
+            ChunkSchedule::Adaptive => {
+                let start = self.next?;
+                let workers = workers.max(1) as u128;
+                // u128 because start..=end may hold 2^64 values
+                let remaining = (self.end - start) as u128 + 1;
+                let timed = (progress.hash_rate() / workers as f64 * ADAPTIVE_CHUNK_TIME.as_secs_f64()) as u128;
+                let guided = remaining / (2 * workers);
+                let len = timed.min(guided).max(MIN_ADAPTIVE_CHUNK.into()).min(remaining);
+                let chunk_end = start + (len - 1) as u64;
+                self.next = chunk_end.checked_add(1).filter(|&n| n <= self.end);
+                Some((start, chunk_end))
+            }
+        }
+    }
+}
+
+// Shared flag telling every MiningTask holding a clone to give up.
+#[derive(Debug, Clone)]
+pub struct CancelToken {
+    // the last flag is this token's own; the others belong to the tokens it was derived from
+    flags: Vec<sync::Arc<AtomicBool>>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken { flags: vec![sync::Arc::new(AtomicBool::new(false))] }
+    }
+
+    pub fn child(&self) -> CancelToken {
+        // a token that is cancelled along with this one, but can also be cancelled on its own
+        let mut flags = self.flags.clone();
+        flags.push(sync::Arc::new(AtomicBool::new(false)));
+        CancelToken { flags }
+    }
+
+    pub fn cancel(&self) {
+        self.flags.last().unwrap().store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flags.iter().any(|f| f.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> CancelToken {
+        CancelToken::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChunkOutcome {
+    Found(u64),
+    Exhausted,
+    Cancelled,
+}
+
+// State shared by all the MiningTasks searching for one block's proof.
+pub(crate) struct SearchState {
+    cancel: CancelToken,
+    best: AtomicU64,    // lowest proof any task has found so far
+    checked: AtomicU64, // proofs checked so far, updated every CANCEL_CHECK_INTERVAL
+}
+
+impl SearchState {
+    pub fn new(cancel: CancelToken) -> SearchState {
+        SearchState {
+            cancel,
+            best: AtomicU64::new(u64::MAX),
+            checked: AtomicU64::new(0),
+        }
+    }
+
+    fn progress(&self, started: Instant) -> MiningProgress {
+        MiningProgress {
+            checked: self.checked.load(Ordering::Relaxed),
+            elapsed: started.elapsed(),
+        }
+    }
+}
+
+pub(crate) struct MiningTask<R: PowRule = AnyPowRule> {
     block: sync::Arc<Block>,
+    rule: R,
     start: u64,
     end: u64,
+    search: sync::Arc<SearchState>,
 }
 
 impl MiningTask {
-    pub fn new(block: sync::Arc<Block>, start: u64, end: u64) -> MiningTask {
+    pub fn new(block: sync::Arc<Block>, start: u64, end: u64, search: sync::Arc<SearchState>) -> MiningTask {
+        // search using the block's own proof-of-work rule
+        let rule = block.pow_rule();
+        MiningTask::with_rule(block, rule, start, end, search)
+    }
+}
+
+impl<R: PowRule> MiningTask<R> {
+    pub fn with_rule(block: sync::Arc<Block>, rule: R, start: u64, end: u64, search: sync::Arc<SearchState>) -> MiningTask<R> {
         MiningTask {
-            block: block,
-            start: start,
-            end: end
+            block,
+            rule,
+            start,
+            end,
+            search,
         }
     }
 }
 
-impl Task for MiningTask {
-    type Output = u64;
+impl<R: PowRule> Task for MiningTask<R> {
+    type Output = ChunkOutcome;
 
-    fn run(&self) -> Option<u64> {
-        //must return an Option<Output> value. None means no valid proof found, Some(p) means p is valid proof
-        let mut p = self.start;
-        while p <= self.end {
-            if self.block.is_valid_for_proof(p){
-                return Some(p);
+    fn run(&self) -> Option<ChunkOutcome> {
+        //always reports an outcome, so mine_range can tell when every chunk has finished
+        //gives up within CANCEL_CHECK_INTERVAL proofs of the token being cancelled,
+        //or of passing a proof another task already found (nothing lower is left here)
+        let hasher = self.block.proof_hasher();
+        let mut outcome = ChunkOutcome::Exhausted;
+        let mut checked = 0; // not yet added to self.search.checked
+        for p in self.start..=self.end {
+            if p == self.start || checked == CANCEL_CHECK_INTERVAL {
+                self.search.checked.fetch_add(checked, Ordering::Relaxed);
+                checked = 0;
+                if self.search.cancel.is_cancelled() {
+                    outcome = ChunkOutcome::Cancelled;
+                    break;
+                }
+                if p > self.search.best.load(Ordering::Relaxed) {
+                    break;
+                }
+            }
+            checked += 1;
+            if self.rule.accepts(&hasher.hash_for_proof(p)) {
+                self.search.best.fetch_min(p, Ordering::Relaxed);
+                outcome = ChunkOutcome::Found(p);
+                break;
             }
-            p += 1;
         }
-        return None;
+        self.search.checked.fetch_add(checked, Ordering::Relaxed);
+        Some(outcome)
     }
 }
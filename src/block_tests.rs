@@ -1,13 +1,20 @@
 #[cfg(test)]
 mod block_tests {
-    use crate::block::Block;
-    use std::{fmt::Write, time::Instant};
+    use crate::block::{
+        split_range, Block, CancelToken, ChunkOutcome, ChunkSchedule, Chunker, Hash, MiningError, MiningOptions,
+        MiningProgress, MiningTask, SearchState, ValidationError, MAX_DATA_LEN,
+    };
+    use crate::pow::{Difficulty, PowRule, Target};
+    use crate::queue::Task;
+    use sha2::{Digest, Sha256};
+    use std::time::{Duration, Instant};
+    use std::{fmt::Write, sync, thread};
 
     // Test correctness of Block::initial and Block::next
     // Test Block.hash_string_for_proof and Block.hash_for_proof
     #[test]
     fn create_hash_for_proof() {
-        let mut b0 = Block::initial(16);
+        let mut b0 = Block::initial_legacy(16);
         let b0_hash_string = b0.hash_string_for_proof(56231);
         assert_eq!("0000000000000000000000000000000000000000000000000000000000000000:0:16::56231", b0_hash_string);
         let b0_hash_for_proof = b0.hash_for_proof(56231);
@@ -24,10 +31,41 @@ mod block_tests {
         assert_eq!("9b4417b36afa6d31c728eed7abc14dd84468fdb055d8f3cbe308b0179df40000", b1_hashed_string);
     }
 
+    // Test that the allocation-free hashing matches hashing the hash string itself
+    #[test]
+    fn hash_matches_hash_string() {
+        let mut b0 = Block::initial_legacy(16);
+        b0.set_proof(56231);
+        let mut b1 = Block::next(&b0, String::from("colons: and ünïcödé"));
+        b1.set_proof(u64::MAX);
+        let b2 = Block::next(&b1, "x".repeat(1000));
+        for block in [Block::initial_legacy(0), Block::initial_legacy(255), b0, b1, b2] {
+            for proof in [0, 7, 10, 99, 100, 123456789, u64::MAX] {
+                let expected = Sha256::digest(block.hash_string_for_proof(proof));
+                assert_eq!(expected, block.hash_for_proof(proof));
+            }
+        }
+    }
+
+    // Test that the precomputed ProofHasher agrees with Block.hash_for_proof
+    #[test]
+    fn proof_hasher() {
+        let mut b0 = Block::initial(16);
+        b0.set_proof(56231);
+        let long_data = "a fairly long message, so the hash string is several SHA-256 blocks long: ".repeat(5);
+        for block in [b0.clone(), Block::next(&b0, String::from("message")), Block::next(&b0, long_data)] {
+            let hasher = block.proof_hasher();
+            for proof in [0, 1, 9, 10, 2159, 56231, 1 << 32, u64::MAX - 1, u64::MAX] {
+                assert_eq!(block.hash_for_proof(proof), hasher.hash_for_proof(proof));
+            }
+        }
+        assert_eq!(b0.hash(), b0.proof_hasher().hash_for_proof(56231));
+    }
+
     // Test Block.is_valid_for_proof
     #[test]
     fn is_valid_for_proof() {
-        let mut b0 = Block::initial(19);
+        let mut b0 = Block::initial_legacy(19);
         b0.set_proof(87745);
         assert_eq!(true, b0.is_valid_for_proof(87745));
         let mut b1 = Block::next(&b0, String::from("hash example 1234"));
@@ -37,6 +75,35 @@ mod block_tests {
         assert_eq!(false, b1.is_valid_for_proof(346082));
     }
 
+    // Test Block.validate and Block.validate_next error reporting
+    #[test]
+    fn validation_errors() {
+        let mut b0 = Block::initial_legacy(19);
+        assert_eq!(Err(ValidationError::MissingProof), b0.validate());
+        assert_eq!(
+            Err(ValidationError::InsufficientWork { difficulty: 19.into(), proof: 12 }),
+            b0.validate_for_proof(12)
+        );
+        b0.set_proof(87745);
+        assert_eq!(Ok(()), b0.validate());
+
+        let mut b1 = Block::next(&b0, String::from("hash example 1234"));
+        b1.set_proof(1407891);
+        assert_eq!(Ok(()), b1.validate_next(&b0));
+        let mut b2 = Block::next(&b1, String::new());
+        b2.set_proof(0);
+        assert_eq!(
+            Err(ValidationError::BadParentLink { expected: b0.hash(), found: b1.hash() }),
+            b2.validate_next(&b0)
+        );
+
+        let big = Block::next(&b0, "x".repeat(MAX_DATA_LEN + 1));
+        assert_eq!(
+            Err(ValidationError::OversizedData { len: MAX_DATA_LEN + 1, max: MAX_DATA_LEN }),
+            big.validate_for_proof(0)
+        );
+    }
+
     // Test MiningTask Implementation
     #[test]
     fn mining_task() {
@@ -48,10 +115,159 @@ mod block_tests {
 
     }
 
+    // Test that a MiningTask stops soon after its cancel token is set
+    #[test]
+    fn mining_task_cancel() {
+        // no proof for difficulty 255 will realistically be found, so only cancellation ends the task
+        let block = sync::Arc::new(Block::initial(255));
+
+        let cancelled = CancelToken::new();
+        cancelled.cancel();
+        let task = MiningTask::new(block.clone(), 0, u64::MAX, sync::Arc::new(SearchState::new(cancelled)));
+        assert_eq!(Some(ChunkOutcome::Cancelled), task.run());
+
+        let cancel = CancelToken::new();
+        let task = MiningTask::new(block, 0, u64::MAX, sync::Arc::new(SearchState::new(cancel.clone())));
+        let worker = thread::spawn(move || task.run());
+        thread::sleep(Duration::from_millis(50));
+        cancel.cancel();
+        assert_eq!(Some(ChunkOutcome::Cancelled), worker.join().unwrap());
+    }
+
+    // Test that mining reports an error instead of hanging when there is no proof to find
+    #[test]
+    fn mine_range_errors() {
+        let block = Block::initial(255);
+        assert_eq!(Err(MiningError::NotFound), block.mine_range(3, 0, 10_000, 7));
+        assert_eq!(Err(MiningError::NotFound), block.mine_range_serial(3, 0, 10_000, 7));
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        assert_eq!(Err(MiningError::Cancelled), block.mine_range_with_cancel(3, 0, u64::MAX / 2, 7, &cancel));
+
+        // cancelling a child token doesn't affect its parent
+        let parent = CancelToken::new();
+        let child = parent.child();
+        child.cancel();
+        assert!(child.is_cancelled());
+        assert!(!parent.is_cancelled());
+        parent.cancel();
+        assert!(parent.child().is_cancelled());
+    }
+
+    // Test that ranges are split into disjoint chunks covering exactly start..=end
+    #[test]
+    fn split_ranges() {
+        assert_eq!(vec![(10, 13), (14, 17), (18, 20)], split_range(10, 20, 3));
+        assert_eq!(vec![(5, 5), (6, 6)], split_range(5, 6, 10));
+        assert_eq!(vec![(7, 7)], split_range(7, 7, 0));
+        assert!(split_range(8, 7, 4).is_empty());
+        assert_eq!(
+            vec![(0, u64::MAX / 2), (u64::MAX / 2 + 1, u64::MAX)],
+            split_range(0, u64::MAX, 2)
+        );
+
+        for &(start, end, chunks) in &[(1_000_000, 2_000_000, 2345), (u64::MAX - 1000, u64::MAX, 7), (3, 1 << 40, 999)] {
+            let ranges = split_range(start, end, chunks);
+            assert!(ranges.len() as u64 <= chunks);
+            assert_eq!(start, ranges[0].0);
+            assert_eq!(end, ranges.last().unwrap().1);
+            for pair in ranges.windows(2) {
+                assert!(pair[0].0 <= pair[0].1);
+                assert_eq!(pair[0].1 + 1, pair[1].0);
+            }
+        }
+    }
+
+    // Test that mining only considers proofs inside the requested range
+    #[test]
+    fn mine_range_offsets() {
+        // 385 is the lowest valid proof for this block (see basic_mine)
+        let block = Block::initial_legacy(7);
+        assert_eq!(Ok(385), block.mine_range(1, 0, 1000, 4));
+        assert_eq!(Ok(385), block.mine_range_serial(1, 0, 1000, 4));
+        assert_eq!(Ok(385), block.mine_range(1, 385, 1000, 4));
+
+        for &(start, end) in &[(386, 100_000), (1_000_000, 2_000_000), (u64::MAX - 100_000, u64::MAX)] {
+            for workers in [1, 3] {
+                let p = block.mine_range(workers, start, end, 17).unwrap();
+                assert!(start <= p && p <= end, "proof {} outside {}..={}", p, start, end);
+                assert!(block.is_valid_for_proof(p));
+            }
+            let p = block.mine_range_serial(1, start, end, 17).unwrap();
+            assert!(start <= p && p <= end, "proof {} outside {}..={}", p, start, end);
+        }
+
+        assert_eq!(Err(MiningError::NotFound), block.mine_range(2, 386, 385, 4));
+    }
+
+    // Test that lowest-proof mining agrees with serial mining for any number of workers
+    #[test]
+    fn mine_lowest() {
+        let mut serial = Block::initial(10);
+        serial.mine_serial();
+        for workers in [1, 2, 5, 8] {
+            let mut b = Block::initial(10);
+            b.mine_lowest(workers);
+            assert_eq!(serial.proof(), b.proof());
+        }
+
+        let mut serial_next = Block::next(&serial, String::from("lowest"));
+        serial_next.mine_serial();
+        let block = Block::next(&serial, String::from("lowest"));
+        for chunks in [1, 3, 64, 10_000] {
+            assert_eq!(serial_next.proof(), block.mine_range_lowest(4, 0, 1 << 16, chunks).ok());
+        }
+        let p = serial_next.proof().unwrap();
+        assert_eq!(Ok(p), block.mine_range_lowest(3, p, 1 << 16, 5));
+        assert_eq!(Err(MiningError::NotFound), block.mine_range_lowest(3, 0, p - 1, 5));
+    }
+
+    // Test mining against a deadline while observing progress
+    #[test]
+    fn mine_deadline_and_progress() {
+        let mut reports = Vec::<MiningProgress>::new();
+        let mut observe = |p: &MiningProgress| reports.push(*p);
+        let begin = Instant::now();
+        let options = MiningOptions {
+            deadline: Some(begin + Duration::from_millis(300)),
+            progress: Some(&mut observe),
+            progress_interval: Duration::from_millis(50),
+            ..MiningOptions::default()
+        };
+        let result = Block::initial_legacy(255).mine_range_with(2, 0, u64::MAX, 8, options);
+        let progress = match result {
+            Err(MiningError::TimedOut(progress)) => progress,
+            other => panic!("expected a timeout, got {:?}", other),
+        };
+        assert!(begin.elapsed() >= Duration::from_millis(300));
+        assert!(progress.elapsed <= begin.elapsed());
+        assert!(progress.checked > 0);
+        assert!(progress.hash_rate() > 0.0);
+
+        // periodic reports, then a final one once mining has stopped
+        assert!(reports.len() >= 3, "only {} progress reports", reports.len());
+        assert!(reports.windows(2).all(|w| w[0].checked <= w[1].checked));
+        assert!(reports.last().unwrap().checked >= progress.checked);
+
+        // a deadline that isn't reached doesn't change the result
+        let block = Block::initial_legacy(7);
+        let mut final_report = None;
+        let mut observe = |p: &MiningProgress| final_report = Some(*p);
+        let options = MiningOptions {
+            deadline: Some(Instant::now() + Duration::from_secs(60)),
+            progress: Some(&mut observe),
+            lowest: true,
+            ..MiningOptions::default()
+        };
+        assert_eq!(Ok(385), block.mine_for_proof_with(3, options));
+        assert!(final_report.unwrap().checked >= 386);
+    }
+
     // Test Block.mine_range with difficult = 7
     #[test]
     fn basic_mine() {
-        let mut b0 = Block::initial(7);
+        let mut b0 = Block::initial_legacy(7);
         b0.mine(1);
         assert_eq!("0000000000000000000000000000000000000000000000000000000000000000:0:7::385", format!("{}", b0.hash_string()));
         assert_eq!("379bf2fb1a558872f09442a45e300e72f00f03f2c6f4dd29971f67ea4f3d5300", format!("{:02x}", b0.hash()));
@@ -68,7 +284,7 @@ mod block_tests {
     // Test Block.mine_range with difficult = 20
     #[test]
     fn difficult_mine() {
-        let mut b0 = Block::initial(20);
+        let mut b0 = Block::initial_legacy(20);
         b0.mine(1);
         assert_eq!("0000000000000000000000000000000000000000000000000000000000000000:0:20::1209938", format!("{}", b0.hash_string()));
         assert_eq!("19e2d3b3f0e2ebda3891979d76f957a5d51e1ba0b43f4296d8fb37c470600000", format!("{:02x}", b0.hash()));
@@ -109,4 +325,159 @@ mod block_tests {
         assert!(time_taken_5_workers < time_taken_1_worker);
     }
 
+    // Test that the default search range grows with the difficulty without overflowing
+    #[test]
+    fn search_range_saturates() {
+        let mut block = Block::initial(10);
+        assert_eq!(8 << 10, block.search_range_end());
+        block.set_difficulty(60);
+        assert_eq!(1 << 63, block.search_range_end());
+        for bits in [61, 64, 200, 256] {
+            block.set_difficulty(Difficulty::new(bits).unwrap());
+            assert_eq!(u64::MAX, block.search_range_end());
+        }
+        block.set_target(Target::from_bytes([0; 32]));
+        assert_eq!(u64::MAX, block.search_range_end());
+    }
+
+    // Test that mining moves on to the next window when the first one holds no proof
+    #[test]
+    fn mine_past_first_window() {
+        let mut b0 = Block::initial_legacy(4);
+        b0.mine_serial();
+        assert_eq!(Some(35), b0.proof());
+        // the first proof for this block is past 8 * 2^4
+        let unlucky = Block::next(&b0, String::from("unlucky 3929"));
+        assert_eq!(128, unlucky.search_range_end());
+        assert_eq!(Err(MiningError::NotFound), unlucky.mine_range(2, 0, 128, 8));
+        assert_eq!(Ok(150), unlucky.mine_for_proof(2));
+
+        let mut checked = 0;
+        let mut observe = |p: &MiningProgress| checked = p.checked;
+        let options = MiningOptions { lowest: true, progress: Some(&mut observe), ..MiningOptions::default() };
+        assert_eq!(Ok(150), unlucky.mine_for_proof_with(3, options));
+        assert!(checked > 150, "progress should count both windows, got {}", checked);
+
+        let mut b1 = unlucky.clone();
+        b1.mine(2);
+        assert_eq!(Some(150), b1.proof());
+        assert_eq!(0, b1.extra_nonce());
+
+        let options = MiningOptions { cancel: CancelToken::new(), ..MiningOptions::default() };
+        options.cancel.cancel();
+        assert_eq!(Err(MiningError::Cancelled), unlucky.clone().mine_with(2, options));
+    }
+
+    // Test that the extra nonce is hashed when set, and used once the u64 proofs run out
+    #[test]
+    fn extra_nonce() {
+        let mut block = Block::initial_legacy(8);
+        block.set_proof(7);
+        let plain = block.hash();
+        block.set_extra_nonce(3);
+        assert_eq!(None, block.proof());
+        block.set_proof(7);
+        assert_eq!(format!("{:0>64}+3:0:8::7", ""), block.hash_string());
+        assert_ne!(plain, block.hash());
+        assert_eq!(Sha256::digest(block.hash_string().as_bytes()), block.hash());
+        assert_eq!(block.hash(), block.proof_hasher().hash_for_proof(7));
+        assert_eq!(0, Block::next(&block, String::new()).extra_nonce());
+
+        // no proof in the last few u64 values, so mining continues from 0 with extra nonce 1
+        let mut block = Block::initial_legacy(8);
+        let first = u64::MAX - 3;
+        assert!((first..=u64::MAX).all(|p| !block.is_valid_for_proof(p)));
+        block.mine_from(2, first, MiningOptions::default()).unwrap();
+        assert_eq!(1, block.extra_nonce());
+        let mut serial = Block::initial_legacy(8);
+        serial.set_extra_nonce(1);
+        serial.mine_serial();
+        assert_eq!(serial.proof(), block.proof());
+        assert!(block.is_valid());
+    }
+
+    // Test that adaptive chunks cover the range in order, and grow with the hash rate
+    #[test]
+    fn adaptive_chunks() {
+        let slow = MiningProgress { checked: 0, elapsed: Duration::ZERO };
+        let fast = MiningProgress { checked: 100_000_000, elapsed: Duration::from_secs(1) };
+        for (start, end) in [(0, 0), (5, 100_000), (0, 1 << 40), (u64::MAX - 5000, u64::MAX), (0, u64::MAX)] {
+            let mut chunker = Chunker::new(ChunkSchedule::Adaptive, start, end);
+            assert_eq!(8, chunker.in_flight_limit(4));
+            let (mut next, mut count) = (start as u128, 0);
+            while let Some((s, e)) = chunker.next_chunk(4, if count < 3 { &slow } else { &fast }) {
+                assert_eq!(next, s as u128);
+                assert!(s <= e);
+                next = e as u128 + 1;
+                count += 1;
+                if count == 1000 {
+                    break;
+                }
+            }
+            assert!(next == end as u128 + 1 || count == 1000);
+        }
+
+        // 1024 proofs at first; 20ms at 25M proofs/s per worker later, while that is under 1/8 of what's left
+        let mut chunker = Chunker::new(ChunkSchedule::Adaptive, 0, 1 << 40);
+        assert_eq!(Some((0, 1023)), chunker.next_chunk(4, &slow));
+        assert_eq!(Some((1024, 1024 + 500_000 - 1)), chunker.next_chunk(4, &fast));
+        let mut chunker = Chunker::new(ChunkSchedule::Adaptive, 0, 80_000);
+        assert_eq!(Some((0, 10_000 - 1)), chunker.next_chunk(4, &fast));
+        assert_eq!(None, Chunker::new(ChunkSchedule::Adaptive, 5, 4).next_chunk(4, &fast));
+
+        let mut fixed = Chunker::new(ChunkSchedule::Fixed(3), 0, 8);
+        assert_eq!(usize::MAX, fixed.in_flight_limit(4));
+        assert_eq!(Some((0, 2)), fixed.next_chunk(4, &fast));
+    }
+
+    // Test that adaptive mining finds the same proofs as fixed chunks, the lowest even when not asked to
+    #[test]
+    fn mine_range_adaptive() {
+        let block = Block::initial(12);
+        let lowest = block.mine_range_lowest(2, 0, 1 << 20, 16).unwrap();
+        for workers in [1, 3] {
+            let options = MiningOptions { lowest: true, ..MiningOptions::default() };
+            assert_eq!(Ok(lowest), block.mine_range_with(workers, 0, 1 << 20, ChunkSchedule::Adaptive, options));
+            let options = MiningOptions::default();
+            assert_eq!(Ok(lowest), block.mine_range_with(workers, 0, 1 << 20, ChunkSchedule::Adaptive, options));
+        }
+        let options = MiningOptions::default();
+        assert_eq!(Err(MiningError::NotFound), block.mine_range_with(2, 0, lowest - 1, ChunkSchedule::Adaptive, options));
+    }
+
+    // A rule that panics on about 1 in 256 hashes, and accepts none.
+    #[derive(Clone)]
+    struct PanickyRule;
+
+    impl PowRule for PanickyRule {
+        fn accepts(&self, hash: &Hash) -> bool {
+            assert!(hash[0] != 0, "unlucky hash");
+            false
+        }
+    }
+
+    // Test that a chunk that panicked fails mining, rather than counting as searched
+    #[test]
+    fn mine_range_panics() {
+        let block = Block::initial(8);
+        for schedule in [ChunkSchedule::Fixed(16), ChunkSchedule::Adaptive] {
+            for lowest in [false, true] {
+                let options = MiningOptions { lowest, ..MiningOptions::default() };
+                match block.mine_range_with_rule(PanickyRule, 2, 0, 100_000, schedule, options) {
+                    Err(MiningError::Panicked(panic)) => assert_eq!("unlucky hash", panic.message),
+                    other => panic!("expected a panic to be reported, got {:?}", other),
+                }
+            }
+        }
+    }
+
+    // Test that mining without workers fails at once, rather than waiting for chunks nothing checks
+    #[test]
+    fn mine_without_workers() {
+        let block = Block::initial(8);
+        assert_eq!(Err(MiningError::NoWorkers), block.mine_range(0, 0, 1000, 4));
+        let options = MiningOptions { lowest: true, ..MiningOptions::default() };
+        assert_eq!(Err(MiningError::NoWorkers), block.mine_range_with(0, 0, 1000, ChunkSchedule::Adaptive, options));
+        assert_eq!(Err(MiningError::NoWorkers), block.clone().mine_with(0, MiningOptions::default()));
+    }
 }
@@ -0,0 +1,324 @@
+use crate::block::{Block, Hash, ValidationError};
+use crate::clock::{Clock, SystemClock};
+use crate::encoding::Encoding;
+use crate::hasher::HashFunction;
+use crate::pow::{Difficulty, PowRuleKind, Target};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// a block's timestamp has to be after the median of this many previous blocks' timestamps
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+// default for how far (in seconds) a block's timestamp may be ahead of the chain's clock
+pub const DEFAULT_MAX_FUTURE_DRIFT: u64 = 2 * 60 * 60;
+
+// a retarget changes the work per block by at most this factor, up or down
+pub const MAX_RETARGET_FACTOR: u64 = 4;
+
+// a retarget interval or window spans at most this many blocks
+pub const MAX_RETARGET_WINDOW: u64 = 1 << 20;
+
+// How a chain adjusts the difficulty of its blocks so they come about `spacing` seconds apart.
+// Needs block timestamps: in chains without them the difficulty never changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Retarget {
+    #[default]
+    Off,
+    // Every `interval` blocks, scale the target by how long the last `interval` blocks took
+    // compared to `spacing` each, as in Bitcoin.
+    FixedWindow { interval: u64, spacing: u64 },
+    // Every block, scale the average target of the last `window` blocks by how long they took.
+    MovingAverage { window: u64, spacing: u64 },
+}
+
+impl Retarget {
+    pub fn check(&self) -> Result<(), ValidationError> {
+        // an interval or window has to span at least one block, and no more than MAX_RETARGET_WINDOW
+        let window = match *self {
+            Retarget::Off => return Ok(()),
+            Retarget::FixedWindow { interval, .. } => interval,
+            Retarget::MovingAverage { window, .. } => window,
+        };
+        if !(1..=MAX_RETARGET_WINDOW).contains(&window) {
+            return Err(ValidationError::BadRetargetWindow { window, max: MAX_RETARGET_WINDOW });
+        }
+        Ok(())
+    }
+}
+
+// Rules every block in a chain has to follow, fixed when the chain is created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainParams {
+    pub hash_function: HashFunction,
+    pub pow_rule: PowRuleKind,
+    pub timestamps: bool,      // every block has a timestamp, checked against earlier blocks and the clock
+    pub max_future_drift: u64, // seconds a timestamp may be ahead of the clock, if `timestamps` is set
+    pub retarget: Retarget,
+    pub encoding: Encoding,
+}
+
+impl Default for ChainParams {
+    fn default() -> ChainParams {
+        ChainParams {
+            hash_function: HashFunction::default(),
+            pow_rule: PowRuleKind::default(),
+            timestamps: false,
+            max_future_drift: DEFAULT_MAX_FUTURE_DRIFT,
+            retarget: Retarget::default(),
+            encoding: Encoding::default(),
+        }
+    }
+}
+
+impl ChainParams {
+    pub fn check(&self, block: &Block) -> Result<(), ValidationError> {
+        if block.hash_function() != self.hash_function {
+            return Err(ValidationError::HashFunctionMismatch {
+                expected: self.hash_function,
+                found: block.hash_function(),
+            });
+        }
+        if block.pow_rule_kind() != self.pow_rule {
+            return Err(ValidationError::PowRuleMismatch { expected: self.pow_rule, found: block.pow_rule_kind() });
+        }
+        if block.encoding() != self.encoding {
+            return Err(ValidationError::EncodingMismatch { expected: self.encoding, found: block.encoding() });
+        }
+        match (self.timestamps, block.timestamp()) {
+            (true, None) => Err(ValidationError::MissingTimestamp),
+            (false, Some(_)) => Err(ValidationError::UnexpectedTimestamp),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Blockchain {
+    params: ChainParams,
+    blocks: Vec<Block>,
+    heights: HashMap<Hash, u64>, // block hash -> height, for lookup by hash
+    clock: Arc<dyn Clock>,
+}
+
+impl Blockchain {
+    pub fn new(genesis: Block) -> Result<Blockchain, ValidationError> {
+        // start a chain from a mined block created by Block::initial, taking its settings as the chain's
+        let params = ChainParams {
+            hash_function: genesis.hash_function(),
+            pow_rule: genesis.pow_rule_kind(),
+            timestamps: genesis.timestamp().is_some(),
+            encoding: genesis.encoding(),
+            ..ChainParams::default()
+        };
+        Blockchain::with_params(params, genesis)
+    }
+
+    pub fn with_params(params: ChainParams, genesis: Block) -> Result<Blockchain, ValidationError> {
+        Blockchain::with_clock(params, genesis, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(params: ChainParams, genesis: Block, clock: Arc<dyn Clock>) -> Result<Blockchain, ValidationError> {
+        // like with_params, but timestamps are checked against (and mine_next takes them from) `clock`
+        params.retarget.check()?;
+        if genesis.generation() != 0 {
+            return Err(ValidationError::BadGeneration { expected: 0, found: genesis.generation() });
+        }
+        if *genesis.prev_hash() != Hash::default() {
+            return Err(ValidationError::BadParentLink { expected: Hash::default(), found: *genesis.prev_hash() });
+        }
+        params.check(&genesis)?;
+        genesis.validate()?;
+        let mut heights = HashMap::new();
+        heights.insert(genesis.hash(), 0);
+        let chain = Blockchain {
+            params,
+            blocks: Vec::new(),
+            heights,
+            clock,
+        };
+        chain.check_timestamp(&genesis)?;
+        Ok(Blockchain { blocks: vec![genesis], ..chain })
+    }
+
+    pub fn mine_genesis(difficulty: impl Into<Difficulty>, workers: usize) -> Blockchain {
+        Blockchain::mine_genesis_with(ChainParams::default(), difficulty, workers)
+    }
+
+    pub fn mine_genesis_with(params: ChainParams, difficulty: impl Into<Difficulty>, workers: usize) -> Blockchain {
+        Blockchain::mine_genesis_with_clock(params, difficulty, workers, Arc::new(SystemClock))
+    }
+
+    pub fn mine_genesis_with_clock(
+        params: ChainParams,
+        difficulty: impl Into<Difficulty>,
+        workers: usize,
+        clock: Arc<dyn Clock>,
+    ) -> Blockchain {
+        // the genesis block is timestamped by `clock`, which the chain then keeps using
+        let mut genesis = Block::initial(difficulty);
+        genesis.set_hash_function(params.hash_function);
+        genesis.set_pow_rule(params.pow_rule);
+        genesis.set_encoding(params.encoding);
+        if params.timestamps {
+            genesis.set_timestamp(clock.now());
+        }
+        genesis.mine(workers);
+        Blockchain::with_clock(params, genesis, clock).expect("freshly mined genesis block should be valid")
+    }
+
+    pub fn params(&self) -> &ChainParams {
+        &self.params
+    }
+
+    pub fn validate_next(&self, block: &Block) -> Result<(), ValidationError> {
+        // would `block` be accepted as the next block after the current tip?
+        self.params.check(block)?;
+        let (difficulty, target) = self.next_difficulty();
+        block.validate_next_with(self.tip(), difficulty, target)?;
+        self.check_timestamp(block)
+    }
+
+    pub fn next_difficulty(&self) -> (Difficulty, Option<Target>) {
+        // the difficulty and target the next block must have, under the chain's Retarget rule
+        let tip = self.tip();
+        let keep = (tip.difficulty(), tip.target());
+        let next_height = self.height() + 1;
+        let (window, spacing) = match self.params.retarget {
+            _ if !self.params.timestamps => return keep,
+            Retarget::Off => return keep,
+            Retarget::FixedWindow { interval, spacing } if next_height % interval == 0 => (interval, spacing),
+            Retarget::FixedWindow { .. } => return keep,
+            Retarget::MovingAverage { window, spacing } => (window, spacing),
+        };
+
+        // the blocks after `first` are the window; their timestamps span as many gaps as there are blocks
+        let first = next_height.saturating_sub(window.max(1).saturating_add(1));
+        let gaps = self.height() - first;
+        if gaps == 0 {
+            return keep; // only the genesis block so far
+        }
+        let time_of = |h: u64| self.get(h).and_then(Block::timestamp).unwrap_or(0);
+        let expected = spacing.saturating_mul(gaps).max(1);
+        let actual = time_of(self.height()).saturating_sub(time_of(first)).clamp(
+            (expected / MAX_RETARGET_FACTOR).max(1),
+            expected.saturating_mul(MAX_RETARGET_FACTOR),
+        );
+
+        let base = match self.params.retarget {
+            Retarget::MovingAverage { .. } => self.blocks[first as usize + 1..]
+                .iter()
+                .fold(Target::from_bytes([0; 32]), |sum, b| sum.saturating_add(b.work_target().mul_div(1, gaps))),
+            _ => tip.work_target(),
+        };
+        let target = base.mul_div(actual, expected);
+        match self.params.pow_rule {
+            PowRuleKind::Target => {
+                // rounded down the same way Block::set_target does
+                (tip.difficulty(), Target::from_compact(target.to_compact()))
+            }
+            _ => {
+                // the zero-bit rules can only take whole bits
+                let bits = target.difficulty_bits().round().clamp(0.0, Difficulty::MAX.bits().into());
+                (Difficulty::new(bits as u16).unwrap_or(Difficulty::MAX), None)
+            }
+        }
+    }
+
+    fn check_timestamp(&self, block: &Block) -> Result<(), ValidationError> {
+        // with `timestamps`, a block must be later than the median time past, and not too far
+        // ahead of the clock (`block` is the next block, or the genesis block of an empty chain)
+        let Some(timestamp) = block.timestamp().filter(|_| self.params.timestamps) else {
+            return Ok(());
+        };
+        if !self.blocks.is_empty() && timestamp <= self.median_time_past() {
+            return Err(ValidationError::TimestampTooOld { timestamp, median_time_past: self.median_time_past() });
+        }
+        let latest = self.clock.now().saturating_add(self.params.max_future_drift);
+        if timestamp > latest {
+            return Err(ValidationError::TimestampTooFarAhead { timestamp, latest });
+        }
+        Ok(())
+    }
+
+    pub fn median_time_past(&self) -> u64 {
+        // the median timestamp of the last MEDIAN_TIME_SPAN blocks (the upper middle one if
+        // there's an even number of them, as in Bitcoin), or 0 if they have no timestamps
+        let mut times: Vec<u64> = self.blocks.iter().rev().take(MEDIAN_TIME_SPAN).filter_map(Block::timestamp).collect();
+        times.sort_unstable();
+        times.get(times.len() / 2).copied().unwrap_or(0)
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    pub fn push(&mut self, block: Block) -> Result<(), ValidationError> {
+        // append `block` if it correctly extends the chain
+        self.validate_next(&block)?;
+        self.heights.insert(block.hash(), block.generation());
+        self.blocks.push(block);
+        Ok(())
+    }
+
+    pub fn mine_next(&mut self, data: String, workers: usize) -> &Block {
+        let block = self.mine_block(data, workers);
+        self.push(block).expect("freshly mined block should extend the chain");
+        self.tip()
+    }
+
+    pub fn mine_block(&self, data: String, workers: usize) -> Block {
+        // mine the next block after the tip without adding it to the chain
+        let mut block = Block::next(self.tip(), data);
+        let (difficulty, target) = self.next_difficulty();
+        block.set_difficulty(difficulty);
+        if let Some(target) = target {
+            block.set_target(target);
+        }
+        if self.params.timestamps {
+            block.set_timestamp(self.clock.now().max(self.median_time_past() + 1));
+        }
+        block.mine(workers);
+        block
+    }
+
+    pub fn genesis(&self) -> &Block {
+        &self.blocks[0]
+    }
+
+    pub fn tip(&self) -> &Block {
+        self.blocks.last().expect("a chain always contains its genesis block")
+    }
+
+    #[allow(clippy::len_without_is_empty)] // never empty: a chain always holds its genesis block
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn height(&self) -> u64 {
+        self.tip().generation()
+    }
+
+    pub fn get(&self, height: u64) -> Option<&Block> {
+        self.blocks.get(usize::try_from(height).ok()?)
+    }
+
+    pub fn get_by_hash(&self, hash: &Hash) -> Option<&Block> {
+        self.height_of(hash).and_then(|h| self.get(h))
+    }
+
+    pub fn height_of(&self, hash: &Hash) -> Option<u64> {
+        self.heights.get(hash).copied()
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.heights.contains_key(hash)
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Block> {
+        self.blocks.iter()
+    }
+}
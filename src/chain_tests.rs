@@ -0,0 +1,305 @@
+#[cfg(test)]
+mod chain_tests {
+    use crate::block::{Block, Hash, ValidationError};
+    use crate::chain::{Blockchain, ChainParams, Retarget, DEFAULT_MAX_FUTURE_DRIFT, MAX_RETARGET_WINDOW};
+    use crate::pow::{Difficulty, PowRuleKind, Target};
+    use crate::clock::{Clock, ManualClock};
+    use std::sync::Arc;
+
+    const DIFFICULTY: u8 = 8;
+
+    // Test building a chain and looking blocks up by height and hash
+    #[test]
+    fn build_and_lookup() {
+        let mut chain = Blockchain::mine_genesis(DIFFICULTY, 1);
+        chain.mine_next(String::from("first"), 1);
+        chain.mine_next(String::from("second"), 1);
+
+        assert_eq!(3, chain.len());
+        assert_eq!(2, chain.height());
+        assert_eq!(0, chain.genesis().generation());
+        assert_eq!("second", chain.tip().data());
+
+        let first = chain.get(1).unwrap();
+        assert_eq!("first", first.data());
+        assert_eq!(first.prev_hash(), &chain.genesis().hash());
+
+        let first_hash = first.hash();
+        assert_eq!(Some(1), chain.height_of(&first_hash));
+        assert_eq!("first", chain.get_by_hash(&first_hash).unwrap().data());
+        assert!(chain.get(3).is_none());
+        assert!(chain.get_by_hash(&Hash::default()).is_none());
+    }
+
+    // Test that a genesis block must be mined and actually be an initial block
+    #[test]
+    fn genesis_checks() {
+        assert_eq!(ValidationError::MissingProof, Blockchain::new(Block::initial(DIFFICULTY)).unwrap_err());
+
+        let mut genesis = Block::initial(DIFFICULTY);
+        genesis.mine(1);
+        let mut b1 = Block::next(&genesis, String::from("not a genesis block"));
+        b1.mine(1);
+        assert_eq!(
+            ValidationError::BadGeneration { expected: 0, found: 1 },
+            Blockchain::new(b1).unwrap_err()
+        );
+        assert!(Blockchain::new(genesis).is_ok());
+    }
+
+    // Test that blocks which don't extend the tip are rejected
+    #[test]
+    fn reject_bad_blocks() {
+        let mut chain = Blockchain::mine_genesis(DIFFICULTY, 1);
+        let genesis = chain.genesis().clone();
+        let b1 = chain.mine_next(String::from("b1"), 1).clone();
+
+        // unmined
+        let unmined = Block::next(&b1, String::from("unmined"));
+        assert_eq!(Err(ValidationError::MissingProof), chain.push(unmined));
+
+        // wrong parent: follows genesis instead of the tip
+        let mut stale = Block::next(&genesis, String::from("stale"));
+        stale.mine(1);
+        assert_eq!(
+            Err(ValidationError::BadParentLink { expected: b1.hash(), found: genesis.hash() }),
+            chain.push(stale)
+        );
+
+        // wrong difficulty
+        let mut harder = Block::next(&b1, String::from("harder"));
+        harder.set_difficulty(DIFFICULTY + 1);
+        harder.mine(1);
+        assert_eq!(
+            Err(ValidationError::DifficultyMismatch { expected: DIFFICULTY.into(), found: (DIFFICULTY + 1).into() }),
+            chain.push(harder)
+        );
+
+        // same block twice: second copy has the wrong parent
+        let mut b2 = Block::next(&b1, String::from("b2"));
+        b2.mine(1);
+        assert_eq!(Ok(()), chain.push(b2.clone()));
+        assert!(matches!(chain.push(b2), Err(ValidationError::BadParentLink { .. })));
+
+        assert_eq!(3, chain.len());
+    }
+
+    const START: u64 = 1_700_000_000;
+
+    fn timestamped_chain(clock: &Arc<ManualClock>) -> Blockchain {
+        let params = ChainParams { timestamps: true, ..ChainParams::default() };
+        Blockchain::mine_genesis_with_clock(params, DIFFICULTY, 1, clock.clone())
+    }
+
+    fn next_at(chain: &Blockchain, timestamp: u64) -> Block {
+        let mut block = Block::next(chain.tip(), String::from("timed"));
+        block.set_timestamp(timestamp);
+        block.mine(1);
+        block
+    }
+
+    // Test that timestamps are hashed, and are only allowed in chains that use them
+    #[test]
+    fn timestamps_are_hashed() {
+        let mut block = Block::initial_legacy(DIFFICULTY);
+        block.set_timestamp(START);
+        block.set_proof(3);
+        assert_eq!(format!("{:0>64}:0:8:{}::3", "", START), block.hash_string());
+        assert_eq!(block.hash(), block.proof_hasher().hash_for_proof(3));
+        assert_eq!(None, Block::next(&block, String::new()).timestamp());
+
+        let mut chain = Blockchain::mine_genesis(DIFFICULTY, 1);
+        let mut b1 = Block::next(chain.tip(), String::from("timed"));
+        b1.set_timestamp(START);
+        b1.mine(1);
+        assert_eq!(Err(ValidationError::UnexpectedTimestamp), chain.push(b1));
+
+        let clock = Arc::new(ManualClock::new(START));
+        let mut chain = timestamped_chain(&clock);
+        assert!(chain.params().timestamps);
+        let mut b1 = Block::next(chain.tip(), String::from("untimed"));
+        b1.mine(1);
+        assert_eq!(Err(ValidationError::MissingTimestamp), chain.push(b1));
+
+        // a timestamped genesis block makes Blockchain::new use timestamps
+        assert!(Blockchain::new(chain.genesis().clone()).unwrap().params().timestamps);
+    }
+
+    // Test the median-time-past and future drift rules against a manual clock
+    #[test]
+    fn timestamp_rules() {
+        let clock = Arc::new(ManualClock::new(START));
+        let mut chain = timestamped_chain(&clock);
+        assert_eq!(Some(START), chain.genesis().timestamp());
+        assert_eq!(START, chain.median_time_past());
+
+        // mine_next takes the clock's time, but always moves past the median
+        assert_eq!(Some(START + 1), chain.mine_next(String::from("b1"), 1).timestamp());
+        assert_eq!(START + 1, chain.median_time_past());
+        clock.advance(600);
+        assert_eq!(Some(START + 600), chain.mine_next(String::from("b2"), 1).timestamp());
+        assert_eq!(START + 1, chain.median_time_past());
+
+        // earlier than the tip is fine, as long as it's after the median
+        assert_eq!(Ok(()), chain.push(next_at(&chain, START + 2)));
+        assert_eq!(START + 2, chain.median_time_past());
+        let old = next_at(&chain, START + 2);
+        assert_eq!(
+            Err(ValidationError::TimestampTooOld { timestamp: START + 2, median_time_past: START + 2 }),
+            chain.push(old)
+        );
+
+        // the median only covers the last 11 blocks
+        for _ in 0..20 {
+            clock.advance(60);
+            chain.mine_next(String::from("minute"), 1);
+        }
+        let times: Vec<u64> = chain.iter().rev().take(11).map(|b| b.timestamp().unwrap()).collect();
+        assert_eq!(times[5], chain.median_time_past());
+
+        let latest = clock.now() + DEFAULT_MAX_FUTURE_DRIFT;
+        assert_eq!(Ok(()), chain.validate_next(&next_at(&chain, latest)));
+        assert_eq!(
+            Err(ValidationError::TimestampTooFarAhead { timestamp: latest + 1, latest }),
+            chain.push(next_at(&chain, latest + 1))
+        );
+        clock.advance(1);
+        assert_eq!(Ok(()), chain.push(next_at(&chain, latest + 1)));
+
+        // the genesis block can't be from the future either
+        let mut genesis = Block::initial(DIFFICULTY);
+        genesis.set_timestamp(latest + 1_000_000);
+        genesis.mine(1);
+        let params = ChainParams { timestamps: true, ..ChainParams::default() };
+        assert!(matches!(
+            Blockchain::with_clock(params, genesis, clock.clone()),
+            Err(ValidationError::TimestampTooFarAhead { .. })
+        ));
+    }
+
+    fn retargeting_chain(clock: &Arc<ManualClock>, pow_rule: PowRuleKind, retarget: Retarget) -> Blockchain {
+        let params = ChainParams { timestamps: true, pow_rule, retarget, ..ChainParams::default() };
+        let mut genesis = Block::initial(DIFFICULTY);
+        genesis.set_pow_rule(pow_rule);
+        genesis.set_timestamp(clock.now());
+        genesis.mine(1);
+        Blockchain::with_clock(params, genesis, clock.clone()).unwrap()
+    }
+
+    // Test Bitcoin-style retargeting every few blocks
+    #[test]
+    fn fixed_window_retarget() {
+        let clock = Arc::new(ManualClock::new(START));
+        let retarget = Retarget::FixedWindow { interval: 4, spacing: 600 };
+        let mut chain = retargeting_chain(&clock, PowRuleKind::TrailingZeroBits, retarget);
+
+        // four times too fast: two more zero bits from height 4
+        for _ in 0..3 {
+            assert_eq!((DIFFICULTY.into(), None), chain.next_difficulty());
+            clock.advance(150);
+            chain.mine_next(String::from("fast"), 1);
+        }
+        clock.advance(150);
+        let mut stale = Block::next(chain.tip(), String::from("old difficulty"));
+        stale.set_timestamp(clock.now());
+        stale.mine(1);
+        assert_eq!(
+            Err(ValidationError::DifficultyMismatch { expected: (DIFFICULTY + 2).into(), found: DIFFICULTY.into() }),
+            chain.push(stale)
+        );
+        assert_eq!(Difficulty::from(DIFFICULTY + 2), chain.mine_next(String::from("harder"), 1).difficulty());
+
+        // on time: unchanged at the next retarget
+        for _ in 0..4 {
+            clock.advance(600);
+            chain.mine_next(String::from("on time"), 1);
+        }
+        assert_eq!(8, chain.height());
+        assert_eq!(Difficulty::from(DIFFICULTY + 2), chain.tip().difficulty());
+
+        // far too slow: limited to a factor of 4, so two fewer bits
+        for _ in 0..4 {
+            clock.advance(60 * 60);
+            chain.mine_next(String::from("slow"), 1);
+        }
+        assert_eq!(Difficulty::from(DIFFICULTY), chain.tip().difficulty());
+
+        // without timestamps there's nothing to retarget from
+        let params = ChainParams { retarget, ..ChainParams::default() };
+        let mut chain = Blockchain::mine_genesis_with(params, DIFFICULTY, 1);
+        for _ in 0..4 {
+            chain.mine_next(String::from("untimed"), 1);
+        }
+        assert_eq!(Difficulty::from(DIFFICULTY), chain.tip().difficulty());
+    }
+
+    // Test that a retarget interval or window must span at least one block, and not too many
+    #[test]
+    fn retarget_window_bounds() {
+        let clock = Arc::new(ManualClock::new(START));
+        let mut genesis = Block::initial(DIFFICULTY);
+        genesis.set_timestamp(clock.now());
+        genesis.mine(1);
+        for window in [0, MAX_RETARGET_WINDOW + 1, u64::MAX] {
+            for retarget in [
+                Retarget::FixedWindow { interval: window, spacing: 600 },
+                Retarget::MovingAverage { window, spacing: 600 },
+            ] {
+                let params = ChainParams { timestamps: true, retarget, ..ChainParams::default() };
+                assert_eq!(
+                    Some(ValidationError::BadRetargetWindow { window, max: MAX_RETARGET_WINDOW }),
+                    Blockchain::with_clock(params, genesis.clone(), clock.clone()).err()
+                );
+            }
+        }
+
+        // the largest window is fine while the chain is still shorter than it
+        let retarget = Retarget::MovingAverage { window: MAX_RETARGET_WINDOW, spacing: 600 };
+        let mut chain = retargeting_chain(&clock, PowRuleKind::TrailingZeroBits, retarget);
+        clock.advance(600);
+        chain.mine_next(String::from("on time"), 1);
+        assert_eq!((DIFFICULTY.into(), None), chain.next_difficulty());
+    }
+
+    // Test retargeting every block from the average target of a moving window
+    #[test]
+    fn moving_average_retarget() {
+        let clock = Arc::new(ManualClock::new(START));
+        let retarget = Retarget::MovingAverage { window: 4, spacing: 100 };
+        let mut chain = retargeting_chain(&clock, PowRuleKind::Target, retarget);
+        assert_eq!((DIFFICULTY.into(), None), chain.next_difficulty());
+
+        // blocks twice as fast as they should be: the target shrinks every block after the first
+        clock.advance(50);
+        assert_eq!(None, chain.mine_next(String::from("fast"), 1).target());
+        clock.advance(50);
+        let b2 = chain.mine_next(String::from("fast"), 1).clone();
+        let expected = Target::from_difficulty(DIFFICULTY).mul_div(50, 100);
+        assert_eq!(Some(Target::from_compact(expected.to_compact()).unwrap()), b2.target());
+        assert_eq!(PowRuleKind::Target, b2.pow_rule_kind());
+        assert!(b2.is_valid());
+        for _ in 0..6 {
+            clock.advance(50);
+            let before = chain.tip().work_target();
+            assert!(chain.mine_next(String::from("fast"), 1).work_target() < before);
+        }
+
+        // a block with its parent's target is rejected once the target should have moved
+        clock.advance(50);
+        let mut stale = Block::next(chain.tip(), String::from("stale target"));
+        stale.set_timestamp(clock.now());
+        stale.mine(1);
+        let (_, expected) = chain.next_difficulty();
+        assert_ne!(expected, stale.target());
+        assert_eq!(Err(ValidationError::TargetMismatch { expected, found: stale.target() }), chain.push(stale));
+
+        // once blocks come on time, the target settles at the average of the window
+        for _ in 0..8 {
+            clock.advance(100);
+            chain.mine_next(String::from("on time"), 1);
+        }
+        let (_, settled) = chain.next_difficulty();
+        let settled = settled.unwrap().expected_work();
+        assert!((settled / chain.tip().work_target().expected_work() - 1.0).abs() < 0.01);
+    }
+}
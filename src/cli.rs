@@ -0,0 +1,266 @@
+use crate::block::{Block, ChunkSchedule, MiningError, MiningOptions, MiningProgress};
+use crate::chain::{Blockchain, ChainParams};
+use crate::encoding::Encoding;
+use crate::hasher::HashFunction;
+use crate::json::JsonError;
+use crate::pow::{Difficulty, PowRuleKind};
+use std::fmt;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+// The command-line interface of the a3 binary. main.rs only passes it the arguments, so
+// everything here can be tested without spawning processes.
+
+pub const USAGE: &str = "\
+usage:
+  a3 mine [--blocks N] [--difficulty D] [--workers W] [--data TEXT]... [--out FILE]
+          [--hash-function NAME] [--pow-rule NAME] [--encoding NAME] [--timestamps]
+      mine a chain of N blocks after the genesis block, writing it as JSON to FILE (or stdout)
+  a3 verify FILE
+      check every block of a chain file
+  a3 inspect FILE
+      print the blocks of a chain file, with their hashes and proofs
+  a3 bench [--workers W,W,...] [--seconds S] [--hash-function NAME]
+      measure the hash rate for each number of workers";
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String), // bad arguments; the usage text should be shown
+    Io(String, std::io::Error),
+    Json(String, JsonError),
+    Mining(MiningError),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}", message),
+            CliError::Io(path, e) => write!(f, "{}: {}", path, e),
+            CliError::Json(path, e) => write!(f, "{}: {}", path, e),
+            CliError::Mining(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+fn usage(message: impl Into<String>) -> CliError {
+    CliError::Usage(message.into())
+}
+
+// Arguments after the subcommand: `--name value` options, `--name` flags and positional values.
+struct Args {
+    options: Vec<(String, String)>,
+    flags: Vec<String>,
+    positional: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: &[String], option_names: &[&str], flag_names: &[&str]) -> Result<Args, CliError> {
+        let mut parsed = Args { options: Vec::new(), flags: Vec::new(), positional: Vec::new() };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                parsed.positional.push(arg.clone());
+                continue;
+            };
+            if flag_names.contains(&name) {
+                parsed.flags.push(name.to_string());
+            } else if option_names.contains(&name) {
+                let value = args.next().ok_or_else(|| usage(format!("--{} needs a value", name)))?;
+                parsed.options.push((name.to_string(), value.clone()));
+            } else {
+                return Err(usage(format!("unknown option --{}", name)));
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn all(&self, name: &str) -> Vec<&str> {
+        self.options.iter().filter(|(n, _)| n == name).map(|(_, v)| v.as_str()).collect()
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        // the last value given wins
+        self.all(name).last().copied()
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+
+    fn number<T: std::str::FromStr>(&self, name: &str, default: T) -> Result<T, CliError> {
+        match self.get(name) {
+            None => Ok(default),
+            Some(value) => value.parse().map_err(|_| usage(format!("--{} expects a number, not {:?}", name, value))),
+        }
+    }
+
+    fn named<T>(&self, name: &str, from_name: fn(&str) -> Option<T>, default: T) -> Result<T, CliError> {
+        match self.get(name) {
+            None => Ok(default),
+            Some(value) => from_name(value).ok_or_else(|| usage(format!("unknown --{} {:?}", name, value))),
+        }
+    }
+
+    fn file(&self) -> Result<&str, CliError> {
+        match self.positional.as_slice() {
+            [file] => Ok(file),
+            [] => Err(usage("expected a chain file")),
+            _ => Err(usage("expected a single chain file")),
+        }
+    }
+
+    fn no_positional(&self) -> Result<(), CliError> {
+        match self.positional.first() {
+            None => Ok(()),
+            Some(arg) => Err(usage(format!("unexpected argument {:?}", arg))),
+        }
+    }
+}
+
+pub fn run(args: &[String], out: &mut dyn Write) -> Result<(), CliError> {
+    // run the command in `args` (without the program name), writing its output to `out`
+    let Some((command, rest)) = args.split_first() else {
+        return Err(usage("expected a command"));
+    };
+    match command.as_str() {
+        "mine" => mine(rest, out),
+        "verify" => verify(rest, out),
+        "inspect" => inspect(rest, out),
+        "bench" => bench(rest, out),
+        "help" | "--help" | "-h" => writeln!(out, "{}", USAGE).map_err(|e| CliError::Io(String::from("<stdout>"), e)),
+        _ => Err(usage(format!("unknown command {:?}", command))),
+    }
+}
+
+fn write_out(out: &mut dyn Write, text: fmt::Arguments) -> Result<(), CliError> {
+    out.write_fmt(text).map_err(|e| CliError::Io(String::from("<stdout>"), e))
+}
+
+fn mine(args: &[String], out: &mut dyn Write) -> Result<(), CliError> {
+    let args = Args::parse(
+        args,
+        &["blocks", "difficulty", "workers", "data", "out", "hash-function", "pow-rule", "encoding"],
+        &["timestamps"],
+    )?;
+    args.no_positional()?;
+    let blocks: u64 = args.number("blocks", 1)?;
+    let difficulty = Difficulty::new(args.number("difficulty", 16)?).map_err(|e| usage(e.to_string()))?;
+    let workers: usize = args.number("workers", 4)?;
+    if workers == 0 {
+        return Err(usage("--workers must be at least 1"));
+    }
+    let params = ChainParams {
+        hash_function: args.named("hash-function", HashFunction::from_name, HashFunction::default())?,
+        pow_rule: args.named("pow-rule", PowRuleKind::from_name, PowRuleKind::default())?,
+        encoding: args.named("encoding", Encoding::from_name, Encoding::default())?,
+        timestamps: args.flag("timestamps"),
+        ..ChainParams::default()
+    };
+    let data = args.all("data");
+
+    let mut chain = Blockchain::mine_genesis_with(params, difficulty, workers);
+    for i in 1..=blocks {
+        // the --data values in turn, then "block N" once they run out
+        let text = data.get(i as usize - 1).map_or_else(|| format!("block {}", i), |d| d.to_string());
+        chain.mine_next(text, workers);
+    }
+
+    match args.get("out") {
+        None => write_out(out, format_args!("{}\n", chain.to_json())),
+        Some(path) => {
+            std::fs::write(path, chain.to_json() + "\n").map_err(|e| CliError::Io(path.to_string(), e))?;
+            for block in chain.iter() {
+                write_out(out, format_args!("mined block {} with proof {}\n", block.generation(), block.proof().unwrap()))?;
+            }
+            write_out(out, format_args!("wrote {} blocks to {}\n", chain.len(), path))
+        }
+    }
+}
+
+fn load(path: &str) -> Result<Blockchain, CliError> {
+    let json = std::fs::read_to_string(path).map_err(|e| CliError::Io(path.to_string(), e))?;
+    Blockchain::from_json(&json).map_err(|e| CliError::Json(path.to_string(), e))
+}
+
+fn verify(args: &[String], out: &mut dyn Write) -> Result<(), CliError> {
+    let args = Args::parse(args, &[], &[])?;
+    let chain = load(args.file()?)?;
+    write_out(out, format_args!("ok: {} blocks, tip {:02x}\n", chain.len(), chain.tip().hash()))
+}
+
+fn inspect(args: &[String], out: &mut dyn Write) -> Result<(), CliError> {
+    let args = Args::parse(args, &[], &[])?;
+    let chain = load(args.file()?)?;
+    let params = chain.params();
+    write_out(
+        out,
+        format_args!(
+            "{} blocks, hash function {}, proof-of-work rule {}, encoding {}\n",
+            chain.len(),
+            params.hash_function,
+            params.pow_rule,
+            params.encoding
+        ),
+    )?;
+    for block in chain.iter() {
+        write_out(out, format_args!("block {}\n", block.generation()))?;
+        write_out(out, format_args!("  hash        {:02x}\n", block.hash()))?;
+        write_out(out, format_args!("  prev hash   {:02x}\n", block.prev_hash()))?;
+        write_out(out, format_args!("  proof       {}\n", block.proof().unwrap()))?;
+        write_out(out, format_args!("  difficulty  {}\n", block.difficulty()))?;
+        if let Some(target) = block.target() {
+            write_out(out, format_args!("  target      {}\n", target))?;
+        }
+        if let Some(timestamp) = block.timestamp() {
+            write_out(out, format_args!("  timestamp   {}\n", timestamp))?;
+        }
+        write_out(out, format_args!("  data        {:?}\n", block.data()))?;
+    }
+    Ok(())
+}
+
+fn bench(args: &[String], out: &mut dyn Write) -> Result<(), CliError> {
+    let args = Args::parse(args, &["workers", "seconds", "hash-function"], &[])?;
+    args.no_positional()?;
+    let seconds: f64 = args.number("seconds", 2.0)?;
+    // zero would be accepted as a Duration, but there'd be no time to measure anything
+    let duration = Some(seconds)
+        .filter(|&s| s > 0.0)
+        .and_then(|s| Duration::try_from_secs_f64(s).ok())
+        .ok_or_else(|| usage("--seconds must be positive"))?;
+    let workers: Vec<usize> = match args.get("workers") {
+        None => {
+            let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+            std::iter::successors(Some(1), |&w| Some(w * 2)).take_while(|&w| w <= cpus).collect()
+        }
+        Some(list) => list
+            .split(',')
+            .map(|w| w.trim().parse().ok().filter(|&w| w > 0))
+            .collect::<Option<_>>()
+            .ok_or_else(|| usage(format!("--workers expects numbers like 1,2,4, not {:?}", list)))?,
+    };
+
+    // no proof is possible at the full difficulty, so mining runs until the deadline
+    let mut block = Block::initial(0);
+    block.set_difficulty(Difficulty::MAX);
+    block.set_hash_function(args.named("hash-function", HashFunction::from_name, HashFunction::default())?);
+    write_out(out, format_args!("{:>8} {:>16} {:>16}\n", "workers", "hashes/s", "per worker"))?;
+    for w in workers {
+        // the final progress report gives the rate however mining ended, even with an (unlikely) proof
+        let mut rate = 0.0;
+        let mut observe = |p: &MiningProgress| rate = p.hash_rate();
+        let options = MiningOptions {
+            deadline: Some(Instant::now() + duration),
+            progress: Some(&mut observe),
+            ..MiningOptions::default()
+        };
+        match block.mine_range_with(w, 0, u64::MAX, ChunkSchedule::Adaptive, options) {
+            Ok(_) | Err(MiningError::TimedOut(_)) => {}
+            Err(e) => return Err(CliError::Mining(e)),
+        }
+        write_out(out, format_args!("{:>8} {:>16.0} {:>16.0}\n", w, rate, rate / w as f64))?;
+    }
+    Ok(())
+}
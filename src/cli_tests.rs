@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod cli_tests {
+    use crate::cli::{run, CliError};
+    use crate::json::JsonError;
+    use std::path::PathBuf;
+
+    fn run_args(args: &[&str]) -> Result<String, CliError> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let mut out = Vec::new();
+        run(&args, &mut out).map(|_| String::from_utf8(out).unwrap())
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("a3-cli-{}-{}.json", std::process::id(), name))
+    }
+
+    // Test mining a chain to a file, then verifying and inspecting it
+    #[test]
+    fn mine_verify_inspect() {
+        let path = temp_file("chain");
+        let file = path.to_str().unwrap();
+        let output = run_args(&[
+            "mine", "--blocks", "2", "--difficulty", "6", "--workers", "2", "--data", "hello", "--out", file,
+            "--pow-rule", "leading-zeros", "--encoding", "binary", "--timestamps",
+        ])
+        .unwrap();
+        assert!(output.contains("wrote 3 blocks"), "{}", output);
+
+        let output = run_args(&["verify", file]).unwrap();
+        assert!(output.starts_with("ok: 3 blocks, tip "), "{}", output);
+
+        let output = run_args(&["inspect", file]).unwrap();
+        assert!(output.contains("proof-of-work rule leading-zeros, encoding binary"), "{}", output);
+        assert!(output.contains("data        \"hello\""), "{}", output);
+        assert!(output.contains("data        \"block 2\""), "{}", output);
+        assert!(output.contains("timestamp"), "{}", output);
+
+        // a tampered file fails verification, naming the block
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, json.replace("\"hello\"", "\"goodbye\"")).unwrap();
+        match run_args(&["verify", file]) {
+            Err(CliError::Json(name, JsonError::HashMismatch { block: Some(1), .. })) => assert_eq!(file, name),
+            other => panic!("expected a hash mismatch, got {:?}", other),
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(run_args(&["verify", file]), Err(CliError::Io(..))));
+    }
+
+    // Test that mine writes JSON to stdout without --out
+    #[test]
+    fn mine_to_stdout() {
+        let output = run_args(&["mine", "--blocks", "1", "--difficulty", "4", "--workers", "1"]).unwrap();
+        assert!(output.trim_start().starts_with('{'));
+        assert!(output.contains("\"block 1\""));
+    }
+
+    // Test measuring hash rates
+    #[test]
+    fn bench() {
+        let output = run_args(&["bench", "--workers", "1,2", "--seconds", "0.1"]).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(3, lines.len(), "{}", output);
+        let rate: f64 = lines[1].split_whitespace().nth(1).unwrap().parse().unwrap();
+        assert!(rate > 0.0);
+    }
+
+    // Test that bad arguments are reported as usage errors
+    #[test]
+    fn usage_errors() {
+        for args in [
+            &[][..],
+            &["frobnicate"],
+            &["mine", "--blocks"],
+            &["mine", "--blocks", "two"],
+            &["mine", "--difficulty", "257"],
+            &["mine", "--workers", "0"],
+            &["mine", "--hash-function", "md5"],
+            &["mine", "--colour"],
+            &["mine", "extra"],
+            &["verify"],
+            &["verify", "a.json", "b.json"],
+            &["bench", "--workers", "1,x"],
+            &["bench", "--seconds", "-1"],
+            &["bench", "--seconds", "0"],
+            &["bench", "--seconds", "NaN"],
+        ] {
+            assert!(matches!(run_args(args), Err(CliError::Usage(_))), "{:?} should be a usage error", args);
+        }
+        assert!(run_args(&["help"]).unwrap().starts_with("usage:"));
+    }
+}
@@ -0,0 +1,24 @@
+#[cfg(test)]
+mod clock_tests {
+    use crate::clock::{Clock, ManualClock, SystemClock};
+
+    // Test that a manual clock only moves when set or advanced
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::new(1_700_000_000);
+        assert_eq!(1_700_000_000, clock.now());
+        assert_eq!(1_700_000_000, clock.now());
+        clock.advance(600);
+        assert_eq!(1_700_000_600, clock.now());
+        clock.set(5);
+        assert_eq!(5, clock.now());
+    }
+
+    // Test that the system clock reads a plausible time
+    #[test]
+    fn system_clock() {
+        let now = SystemClock.now();
+        assert!(now > 1_600_000_000);
+        assert!(SystemClock.now() >= now);
+    }
+}
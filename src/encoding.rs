@@ -0,0 +1,116 @@
+use crate::pow::DifficultyError;
+use std::fmt;
+
+// version byte at the start of every binary-encoded block
+pub const ENCODING_VERSION: u8 = 1;
+
+// What gets hashed for a block's proof of work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Encoding {
+    // The original "prev:generation:difficulty:data:proof" string, ambiguous because `data`
+    // may itself contain ':'. Only for blocks that must keep their old hashes, e.g. fixtures.
+    Legacy,
+    // The versioned, length-prefixed header from Block::to_bytes, so no two blocks hash
+    // the same bytes.
+    #[default]
+    Binary,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 2] = [Encoding::Legacy, Encoding::Binary];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Legacy => "legacy",
+            Encoding::Binary => "binary",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        Encoding::ALL.into_iter().find(|e| e.name() == name)
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    UnsupportedVersion(u8),
+    Truncated,                  // the bytes end before the block does
+    TrailingBytes(usize),       // this many bytes follow the block
+    UnknownFlags(u8),
+    UnknownHashFunction(u8),
+    UnknownPowRule(u8),
+    BadDifficulty(DifficultyError),
+    BadTarget(u32),             // not the canonical compact encoding of a target
+    InvalidData,                // `data` is not UTF-8
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported encoding version {}", v),
+            DecodeError::Truncated => write!(f, "block encoding is truncated"),
+            DecodeError::TrailingBytes(n) => write!(f, "{} unexpected bytes after the block", n),
+            DecodeError::UnknownFlags(flags) => write!(f, "unknown flags {:#04x}", flags),
+            DecodeError::UnknownHashFunction(id) => write!(f, "unknown hash function id {}", id),
+            DecodeError::UnknownPowRule(id) => write!(f, "unknown proof-of-work rule id {}", id),
+            DecodeError::BadDifficulty(e) => write!(f, "{}", e),
+            DecodeError::BadTarget(bits) => write!(f, "{:#010x} is not a canonical compact target", bits),
+            DecodeError::InvalidData => write!(f, "block data is not valid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// Reads the big-endian fields of an encoded block in order.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if n > self.bytes.len() {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, DecodeError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    pub fn finish(self) -> Result<(), DecodeError> {
+        match self.bytes.len() {
+            0 => Ok(()),
+            n => Err(DecodeError::TrailingBytes(n)),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+#[cfg(test)]
+mod encoding_tests {
+    use crate::block::{Block, ValidationError};
+    use crate::chain::{Blockchain, ChainParams};
+    use crate::encoding::{DecodeError, Encoding};
+    use crate::hasher::HashFunction;
+    use crate::pow::{DifficultyError, PowRuleKind, Target};
+    use sha2::{Digest, Sha256};
+
+    fn binary_block(data: &str) -> Block {
+        let mut b0 = Block::initial(8);
+        b0.mine(1);
+        Block::next(&b0, String::from(data))
+    }
+
+    // Test the exact bytes of a small block
+    #[test]
+    fn layout() {
+        let mut block = Block::initial(7);
+        block.set_encoding(Encoding::Binary);
+        block.set_proof(0x0102);
+        let mut expected = vec![1, 0x08]; // version, flags: proof
+        expected.extend([0; 32]); // previous hash
+        expected.extend([0, 0, 0, 0, 0, 0, 0, 0]); // generation
+        expected.extend([0, 7]); // difficulty
+        expected.extend([0, 0]); // hash function, proof-of-work rule
+        expected.extend([0, 0, 0, 0, 0, 0, 0, 0]); // extra nonce
+        expected.extend([0, 0, 0, 0, 0, 0, 0, 0]); // data length
+        expected.extend([0, 0, 0, 0, 0, 0, 1, 2]); // proof
+        assert_eq!(expected, block.to_bytes());
+        assert_eq!(expected, block.preimage_for_proof(0x0102));
+        assert_eq!(Sha256::digest(&expected), block.hash());
+        assert_eq!(block.hash(), block.proof_hasher().hash_for_proof(0x0102));
+
+        // the hash string of a binary block is its preimage in hex
+        assert_eq!(2 * expected.len(), block.hash_string().len());
+        assert!(block.hash_string().starts_with("0108000000"));
+    }
+
+    // Test that blocks decode to exactly what was encoded
+    #[test]
+    fn round_trip() {
+        let mut blocks = vec![Block::initial(7), binary_block("unmined")];
+        let mut b = binary_block("every field: target, timestamp, extra nonce");
+        b.set_hash_function(HashFunction::Blake2s256);
+        b.set_target(Target::from_difficulty(9).mul_div(3, 2));
+        b.set_timestamp(1_700_000_000);
+        b.set_extra_nonce(u64::MAX);
+        b.mine(1);
+        blocks.push(b);
+        let mut legacy = Block::initial(7);
+        legacy.mine(1);
+        blocks.push(Block::next(&legacy, String::from("legacy, stored in binary: ünïcode")));
+        blocks.push(legacy);
+
+        for block in blocks {
+            let bytes = block.to_bytes();
+            let decoded = Block::from_bytes(&bytes).unwrap();
+            assert_eq!(block, decoded);
+            assert_eq!(bytes, decoded.to_bytes());
+            if block.proof().is_some() {
+                assert_eq!(block.hash(), decoded.hash());
+                assert_eq!(block.validate(), decoded.validate());
+            }
+        }
+    }
+
+    // Test that data containing ':' can't make two blocks hash the same bytes
+    #[test]
+    fn no_ambiguity() {
+        let mut a = Block::initial_legacy(0);
+        a.set_proof(1);
+        let mut b = Block::next(&a, String::from("5:x"));
+        let mut c = Block::next(&a, String::from("x"));
+        c.set_timestamp(5);
+        b.set_proof(9);
+        c.set_proof(9);
+        assert_eq!(b.hash_string(), c.hash_string());
+        assert_eq!(b.hash(), c.hash());
+
+        // the extra nonce sits where data can't imitate it
+        let mut d = Block::next(&a, String::from("x:5"));
+        let mut e = Block::next(&a, String::from("x"));
+        e.set_extra_nonce(5);
+        d.set_proof(9);
+        e.set_proof(9);
+        assert_ne!(d.hash_string(), e.hash_string());
+
+        b.set_encoding(Encoding::Binary);
+        c.set_encoding(Encoding::Binary);
+        b.set_proof(9);
+        c.set_proof(9);
+        assert_ne!(b.preimage_for_proof(9), c.preimage_for_proof(9));
+        assert_ne!(b.hash(), c.hash());
+    }
+
+    // Test that malformed bytes are rejected with the reason
+    #[test]
+    fn decode_errors() {
+        let mut block = binary_block("data");
+        block.set_target(Target::from_difficulty(8));
+        block.set_proof(3);
+        let bytes = block.to_bytes();
+        let with = |i: usize, b: u8| {
+            let mut bytes = bytes.clone();
+            bytes[i] = b;
+            Block::from_bytes(&bytes)
+        };
+
+        assert_eq!(Err(DecodeError::UnsupportedVersion(2)), with(0, 2));
+        assert_eq!(Err(DecodeError::UnknownFlags(0x19)), with(1, 0x19));
+        assert_eq!(Err(DecodeError::BadDifficulty(DifficultyError { bits: 0x0108 })), with(42, 1));
+        assert_eq!(Err(DecodeError::UnknownHashFunction(4)), with(44, 4));
+        assert_eq!(Err(DecodeError::UnknownPowRule(3)), with(45, 3));
+        assert_eq!(Err(DecodeError::BadTarget(0x9f00ffff)), with(46, 0x9f));
+        assert_eq!(Err(DecodeError::InvalidData), with(bytes.len() - 9, 0xff));
+        assert_eq!(Err(DecodeError::Truncated), Block::from_bytes(&bytes[..bytes.len() - 1]));
+        assert_eq!(Err(DecodeError::Truncated), Block::from_bytes(&[]));
+        let mut longer = bytes.clone();
+        longer.extend([0, 0]);
+        assert_eq!(Err(DecodeError::TrailingBytes(2)), Block::from_bytes(&longer));
+
+        // a compact target that rounds to the same value as another one isn't canonical
+        let mut bytes = bytes.clone();
+        bytes[46..50].copy_from_slice(&0x0200_8000u32.to_be_bytes());
+        assert!(Block::from_bytes(&bytes).is_ok());
+        bytes[46..50].copy_from_slice(&0x0300_0080u32.to_be_bytes());
+        assert_eq!(Err(DecodeError::BadTarget(0x0300_0080)), Block::from_bytes(&bytes));
+    }
+
+    // Test mining with either encoding, binary unless legacy is asked for, and that chains only accept their own
+    #[test]
+    fn chain_enforces_encoding() {
+        for encoding in Encoding::ALL {
+            for kind in PowRuleKind::ALL {
+                let params = ChainParams { encoding, pow_rule: kind, ..ChainParams::default() };
+                let mut chain = Blockchain::mine_genesis_with(params, 8, 1);
+                let b1 = chain.mine_next(String::from("either"), 2);
+                assert_eq!(encoding, b1.encoding());
+                assert!(b1.is_valid());
+            }
+        }
+        assert_eq!(Some(Encoding::Binary), Encoding::from_name("binary"));
+        assert_eq!(Encoding::Binary, Block::initial(8).encoding());
+        assert_eq!(Encoding::Legacy, Block::initial_legacy(8).encoding());
+
+        let mut chain = Blockchain::mine_genesis(8, 1);
+        let mut b1 = Block::next(chain.tip(), String::from("legacy"));
+        b1.set_encoding(Encoding::Legacy);
+        b1.mine(1);
+        assert_eq!(
+            Err(ValidationError::EncodingMismatch { expected: Encoding::Binary, found: Encoding::Legacy }),
+            chain.push(b1)
+        );
+    }
+}
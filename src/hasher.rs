@@ -0,0 +1,134 @@
+use crate::block::Hash;
+use blake2::Blake2s256;
+use digest::consts::U32;
+use digest::{Digest, OutputSizeUser};
+use sha2::Sha256;
+use sha3::Sha3_256;
+use std::fmt;
+
+// An incremental hash function producing 32-byte block hashes. Implemented for any
+// 32-byte digest from the RustCrypto crates, plus DoubleSha256 and AnyHasher.
+pub trait BlockHasher: Clone {
+    fn write(&mut self, bytes: &[u8]);
+    fn finish(self) -> Hash;
+}
+
+impl<D> BlockHasher for D
+where
+    D: Digest + OutputSizeUser<OutputSize = U32> + Clone,
+{
+    fn write(&mut self, bytes: &[u8]) {
+        Digest::update(self, bytes);
+    }
+
+    fn finish(self) -> Hash {
+        self.finalize()
+    }
+}
+
+// SHA-256 applied twice, as in Bitcoin.
+#[derive(Debug, Clone, Default)]
+pub struct DoubleSha256(Sha256);
+
+impl BlockHasher for DoubleSha256 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(self) -> Hash {
+        Sha256::digest(self.0.finalize())
+    }
+}
+
+// The hash functions a block (and a chain) can be configured to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum HashFunction {
+    #[default]
+    Sha256,
+    DoubleSha256,
+    Sha3_256,
+    Blake2s256,
+}
+
+impl HashFunction {
+    pub const ALL: [HashFunction; 4] = [
+        HashFunction::Sha256,
+        HashFunction::DoubleSha256,
+        HashFunction::Sha3_256,
+        HashFunction::Blake2s256,
+    ];
+
+    pub fn hasher(self) -> AnyHasher {
+        match self {
+            HashFunction::Sha256 => AnyHasher::Sha256(Sha256::new()),
+            HashFunction::DoubleSha256 => AnyHasher::DoubleSha256(DoubleSha256::default()),
+            HashFunction::Sha3_256 => AnyHasher::Sha3_256(Sha3_256::new()),
+            HashFunction::Blake2s256 => AnyHasher::Blake2s256(Blake2s256::new()),
+        }
+    }
+
+    pub fn hash(self, bytes: &[u8]) -> Hash {
+        let mut hasher = self.hasher();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashFunction::Sha256 => "sha256",
+            HashFunction::DoubleSha256 => "double-sha256",
+            HashFunction::Sha3_256 => "sha3-256",
+            HashFunction::Blake2s256 => "blake2s256",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<HashFunction> {
+        HashFunction::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    pub fn id(self) -> u8 {
+        // how the binary block encoding identifies it: the position in ALL
+        HashFunction::ALL.iter().position(|&f| f == self).unwrap() as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<HashFunction> {
+        HashFunction::ALL.get(usize::from(id)).copied()
+    }
+}
+
+impl fmt::Display for HashFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Hasher state for whichever HashFunction a block was configured with.
+// Not boxed: mining clones one of these for every proof it tries.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum AnyHasher {
+    Sha256(Sha256),
+    DoubleSha256(DoubleSha256),
+    Sha3_256(Sha3_256),
+    Blake2s256(Blake2s256),
+}
+
+impl BlockHasher for AnyHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            AnyHasher::Sha256(h) => h.write(bytes),
+            AnyHasher::DoubleSha256(h) => h.write(bytes),
+            AnyHasher::Sha3_256(h) => h.write(bytes),
+            AnyHasher::Blake2s256(h) => h.write(bytes),
+        }
+    }
+
+    fn finish(self) -> Hash {
+        match self {
+            AnyHasher::Sha256(h) => h.finish(),
+            AnyHasher::DoubleSha256(h) => h.finish(),
+            AnyHasher::Sha3_256(h) => h.finish(),
+            AnyHasher::Blake2s256(h) => h.finish(),
+        }
+    }
+}
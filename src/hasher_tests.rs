@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod hasher_tests {
+    use crate::block::{Block, ValidationError};
+    use crate::chain::{Blockchain, ChainParams};
+    use crate::hasher::{BlockHasher, DoubleSha256, HashFunction};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    // Test each hash function against its published test vector for "abc"
+    #[test]
+    fn known_answers() {
+        let expected = [
+            (HashFunction::Sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (HashFunction::DoubleSha256, "4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358"),
+            (HashFunction::Sha3_256, "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"),
+            (HashFunction::Blake2s256, "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982"),
+        ];
+        for (function, answer) in expected {
+            assert_eq!(answer, hex(&function.hash(b"abc")), "{}", function);
+
+            // feeding the input in pieces gives the same hash
+            let mut hasher = function.hasher();
+            hasher.write(b"a");
+            hasher.write(b"");
+            hasher.write(b"bc");
+            assert_eq!(answer, hex(&hasher.finish()), "{}", function);
+        }
+
+        let mut double = DoubleSha256::default();
+        double.write(b"abc");
+        assert_eq!(HashFunction::DoubleSha256.hash(b"abc"), double.finish());
+    }
+
+    // Test converting hash functions to and from their names
+    #[test]
+    fn names() {
+        for function in HashFunction::ALL {
+            assert_eq!(Some(function), HashFunction::from_name(function.name()));
+            assert_eq!(function.name(), format!("{}", function));
+        }
+        assert_eq!(None, HashFunction::from_name("md5"));
+        assert_eq!(HashFunction::Sha256, HashFunction::default());
+    }
+
+    // Test that blocks hash, mine and validate with their configured hash function
+    #[test]
+    fn blocks_use_their_hash_function() {
+        let sha256 = Block::initial_legacy(8);
+        for function in HashFunction::ALL {
+            let mut b0 = Block::initial_legacy(8);
+            b0.set_hash_function(function);
+            assert_eq!(function.hash(b0.hash_string_for_proof(42).as_bytes()), b0.hash_for_proof(42));
+            assert_eq!(b0.hash_for_proof(42), b0.proof_hasher().hash_for_proof(42));
+            if function != HashFunction::Sha256 {
+                assert_ne!(sha256.hash_for_proof(42), b0.hash_for_proof(42));
+            }
+
+            b0.mine(2);
+            assert!(b0.is_valid());
+            let mut b1 = Block::next(&b0, String::from("inherits the hash function"));
+            assert_eq!(function, b1.hash_function());
+            b1.mine(2);
+            assert_eq!(Ok(()), b1.validate_next(&b0));
+        }
+    }
+
+    // Test that a chain only accepts blocks using the hash function in its parameters
+    #[test]
+    fn chain_enforces_hash_function() {
+        let params = ChainParams { hash_function: HashFunction::Sha3_256, ..ChainParams::default() };
+        let mut chain = Blockchain::mine_genesis_with(params, 8, 1);
+        assert_eq!(HashFunction::Sha3_256, chain.genesis().hash_function());
+        chain.mine_next(String::from("sha3"), 1);
+
+        let mut wrong = Block::next(chain.tip(), String::from("sha256"));
+        wrong.set_hash_function(HashFunction::Sha256);
+        wrong.mine(1);
+        assert_eq!(
+            Err(ValidationError::HashFunctionMismatch { expected: HashFunction::Sha3_256, found: HashFunction::Sha256 }),
+            chain.push(wrong)
+        );
+
+        let mut genesis = Block::initial(8);
+        genesis.mine(1);
+        assert!(matches!(
+            Blockchain::with_params(params, genesis.clone()),
+            Err(ValidationError::HashFunctionMismatch { .. })
+        ));
+        assert_eq!(HashFunction::Sha256, Blockchain::new(genesis).unwrap().params().hash_function);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::block::{Block, Hash, ValidationError};
+use crate::chain::{Blockchain, ChainParams, Retarget};
+use crate::encoding::Encoding;
+use crate::hasher::HashFunction;
+use crate::pow::{Difficulty, PowRuleKind, Target};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// JSON import and export of blocks and chains. Hashes and targets are hex strings, and
+// settings are written by name. Imports are checked as thoroughly as blocks from a miner.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonError {
+    Syntax(String), // not JSON, or not the shape of a block or chain; says where
+    BadField { block: Option<usize>, field: &'static str, value: String },
+    HashMismatch { block: Option<usize>, stated: String, actual: String }, // the "hash" field is wrong
+    Invalid { block: Option<usize>, error: ValidationError },
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = |block: &Option<usize>| block.map_or(String::new(), |i| format!("block {}: ", i));
+        match self {
+            JsonError::Syntax(message) => write!(f, "invalid JSON: {}", message),
+            JsonError::BadField { block, field, value } => write!(f, "{}bad {}: {:?}", at(block), field, value),
+            JsonError::HashMismatch { block, stated, actual } => {
+                write!(f, "{}hash is {}, but the file says {}", at(block), actual, stated)
+            }
+            JsonError::Invalid { block, error } => write!(f, "{}{}", at(block), error),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockJson {
+    prev_hash: String,
+    generation: u64,
+    difficulty: u16,
+    data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proof: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>, // written for mined blocks; checked on import if present
+    hash_function: String,
+    pow_rule: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    extra_nonce: u64,
+    encoding: String,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ParamsJson {
+    hash_function: String,
+    pow_rule: String,
+    timestamps: bool,
+    max_future_drift: u64,
+    retarget: RetargetJson,
+    encoding: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case", deny_unknown_fields)]
+enum RetargetJson {
+    Off,
+    FixedWindow { interval: u64, spacing: u64 },
+    MovingAverage { window: u64, spacing: u64 },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainJson {
+    params: ParamsJson,
+    blocks: Vec<BlockJson>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<[u8; 32]> {
+    // either case, but nothing from_str_radix would also take, like a '+' sign
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut bytes = [0u8; 32];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn syntax(e: serde_json::Error) -> JsonError {
+    JsonError::Syntax(e.to_string())
+}
+
+impl BlockJson {
+    fn from_block(block: &Block) -> BlockJson {
+        BlockJson {
+            prev_hash: to_hex(block.prev_hash()),
+            generation: block.generation(),
+            difficulty: block.difficulty().bits(),
+            data: block.data().to_string(),
+            proof: block.proof(),
+            hash: block.proof().map(|_| to_hex(&block.hash())),
+            hash_function: block.hash_function().name().to_string(),
+            pow_rule: block.pow_rule_kind().name().to_string(),
+            target: block.target().map(|t| t.to_string()),
+            timestamp: block.timestamp(),
+            extra_nonce: block.extra_nonce(),
+            encoding: block.encoding().name().to_string(),
+        }
+    }
+
+    fn to_block(&self, index: Option<usize>) -> Result<Block, JsonError> {
+        // rebuild the block, checking its fields but not yet its proof of work
+        let bad = |field: &'static str, value: &str| JsonError::BadField { block: index, field, value: value.to_string() };
+        let prev_hash = from_hex(&self.prev_hash).ok_or_else(|| bad("prev_hash", &self.prev_hash))?;
+        let difficulty = Difficulty::new(self.difficulty).map_err(|_| bad("difficulty", &self.difficulty.to_string()))?;
+        let hash_function = HashFunction::from_name(&self.hash_function).ok_or_else(|| bad("hash_function", &self.hash_function))?;
+        let pow_rule = PowRuleKind::from_name(&self.pow_rule).ok_or_else(|| bad("pow_rule", &self.pow_rule))?;
+        let encoding = Encoding::from_name(&self.encoding).ok_or_else(|| bad("encoding", &self.encoding))?;
+
+        let mut block = Block::from_link(Hash::from(prev_hash), self.generation, self.data.clone());
+        block.set_difficulty(difficulty);
+        block.set_hash_function(hash_function);
+        if let Some(hex) = &self.target {
+            // only targets a block can hold: set_target would round anything else
+            let target = from_hex(hex).map(Target::from_bytes);
+            let target = target.filter(|t| Target::from_compact(t.to_compact()) == Some(*t));
+            block.set_target(target.ok_or_else(|| bad("target", hex))?);
+        }
+        block.set_pow_rule(pow_rule);
+        if let Some(timestamp) = self.timestamp {
+            block.set_timestamp(timestamp);
+        }
+        block.set_extra_nonce(self.extra_nonce);
+        block.set_encoding(encoding);
+        if let Some(proof) = self.proof {
+            block.set_proof(proof);
+        }
+
+        if let Some(stated) = &self.hash {
+            // compared as bytes, so the hex may be in either case
+            let stated_bytes = from_hex(stated).ok_or_else(|| bad("hash", stated))?;
+            let actual = self.proof.map(|_| block.hash());
+            if actual != Some(Hash::from(stated_bytes)) {
+                let actual = actual.map_or_else(|| String::from("none (no proof)"), |h| to_hex(&h));
+                return Err(JsonError::HashMismatch { block: index, stated: stated.clone(), actual });
+            }
+        }
+        Ok(block)
+    }
+}
+
+impl ParamsJson {
+    fn from_params(params: &ChainParams) -> ParamsJson {
+        ParamsJson {
+            hash_function: params.hash_function.name().to_string(),
+            pow_rule: params.pow_rule.name().to_string(),
+            timestamps: params.timestamps,
+            max_future_drift: params.max_future_drift,
+            retarget: match params.retarget {
+                Retarget::Off => RetargetJson::Off,
+                Retarget::FixedWindow { interval, spacing } => RetargetJson::FixedWindow { interval, spacing },
+                Retarget::MovingAverage { window, spacing } => RetargetJson::MovingAverage { window, spacing },
+            },
+            encoding: params.encoding.name().to_string(),
+        }
+    }
+
+    fn to_params(&self) -> Result<ChainParams, JsonError> {
+        let bad = |field: &'static str, value: &str| JsonError::BadField { block: None, field, value: value.to_string() };
+        let retarget = match self.retarget {
+            RetargetJson::Off => Retarget::Off,
+            RetargetJson::FixedWindow { interval, spacing } => Retarget::FixedWindow { interval, spacing },
+            RetargetJson::MovingAverage { window, spacing } => Retarget::MovingAverage { window, spacing },
+        };
+        // checked here as well as by the chain, so the file's field gets the blame
+        retarget.check().map_err(|_| {
+            bad("retarget", &serde_json::to_string(&self.retarget).expect("retarget settings always serialize"))
+        })?;
+        Ok(ChainParams {
+            hash_function: HashFunction::from_name(&self.hash_function).ok_or_else(|| bad("hash_function", &self.hash_function))?,
+            pow_rule: PowRuleKind::from_name(&self.pow_rule).ok_or_else(|| bad("pow_rule", &self.pow_rule))?,
+            timestamps: self.timestamps,
+            max_future_drift: self.max_future_drift,
+            retarget,
+            encoding: Encoding::from_name(&self.encoding).ok_or_else(|| bad("encoding", &self.encoding))?,
+        })
+    }
+}
+
+impl ChainParams {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&ParamsJson::from_params(self)).expect("chain params always serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<ChainParams, JsonError> {
+        serde_json::from_str::<ParamsJson>(json).map_err(syntax)?.to_params()
+    }
+}
+
+impl Block {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&BlockJson::from_block(self)).expect("blocks always serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Block, JsonError> {
+        // A block from to_json. A mined block must also have a valid proof of work; an
+        // unmined one is only checked field by field.
+        let block = serde_json::from_str::<BlockJson>(json).map_err(syntax)?.to_block(None)?;
+        if block.proof().is_some() {
+            block.validate().map_err(|error| JsonError::Invalid { block: None, error })?;
+        }
+        Ok(block)
+    }
+}
+
+impl Blockchain {
+    pub fn to_json(&self) -> String {
+        let chain = ChainJson {
+            params: ParamsJson::from_params(self.params()),
+            blocks: self.iter().map(BlockJson::from_block).collect(),
+        };
+        serde_json::to_string_pretty(&chain).expect("chains always serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Blockchain, JsonError> {
+        // A chain from to_json, rebuilt block by block with all the checks of Blockchain::push.
+        // Errors give the height of the first block that failed.
+        let file = serde_json::from_str::<ChainJson>(json).map_err(syntax)?;
+        let params = file.params.to_params()?;
+        let mut blocks = file.blocks.iter().enumerate().map(|(i, b)| b.to_block(Some(i)));
+        let genesis = match blocks.next() {
+            Some(genesis) => genesis?,
+            None => return Err(JsonError::Syntax(String::from("a chain needs at least a genesis block"))),
+        };
+        let mut chain = Blockchain::with_params(params, genesis).map_err(|error| JsonError::Invalid { block: Some(0), error })?;
+        for (i, block) in (1..).zip(blocks) {
+            chain.push(block?).map_err(|error| JsonError::Invalid { block: Some(i), error })?;
+        }
+        Ok(chain)
+    }
+}
@@ -0,0 +1,165 @@
+#[cfg(test)]
+mod json_tests {
+    use crate::block::{Block, ValidationError};
+    use crate::chain::{Blockchain, ChainParams, Retarget};
+    use crate::clock::{Clock, ManualClock};
+    use crate::encoding::Encoding;
+    use crate::json::JsonError;
+    use crate::pow::{PowRuleKind, Target};
+    use std::sync::Arc;
+
+    fn indent(json: &str) -> String {
+        // a block's JSON as it appears inside a chain's
+        json.lines().map(|l| format!("    {}", l)).collect::<Vec<_>>().join("\n")
+    }
+
+    fn sample_chain() -> Blockchain {
+        let mut chain = Blockchain::mine_genesis(8, 1);
+        chain.mine_next(String::from("first"), 1);
+        chain.mine_next(String::from("second: with \"quotes\" and ünïcode"), 1);
+        chain
+    }
+
+    // Test that blocks survive a round trip, including the optional fields
+    #[test]
+    fn block_round_trip() {
+        let mut block = Block::initial(7);
+        block.mine(1);
+        let json = block.to_json();
+        assert!(json.contains(&format!("\"hash\": \"{:02x}\"", block.hash())));
+        assert!(!json.contains("target"));
+        assert_eq!(Ok(block.clone()), Block::from_json(&json));
+
+        let mut next = Block::next(&block, String::from("all the fields"));
+        next.set_target(Target::from_difficulty(9));
+        next.set_timestamp(1_700_000_000);
+        next.set_extra_nonce(2);
+        next.set_encoding(Encoding::Legacy);
+        assert!(!next.to_json().contains("\"proof\""));
+        assert_eq!(Ok(next.clone()), Block::from_json(&next.to_json()));
+        next.mine(1);
+        assert_eq!(Ok(next.clone()), Block::from_json(&next.to_json()));
+    }
+
+    // Test that hashes written in uppercase hex are read the same
+    #[test]
+    fn uppercase_hex() {
+        let chain = sample_chain();
+        let b1 = chain.get(1).unwrap();
+        let upper = |h: &[u8]| h.iter().map(|b| format!("{:02X}", b)).collect::<String>();
+        let json = b1
+            .to_json()
+            .replace(&format!("{:02x}", b1.hash()), &upper(&b1.hash()))
+            .replace(&format!("{:02x}", b1.prev_hash()), &upper(b1.prev_hash()));
+        assert_ne!(b1.to_json(), json);
+        assert_eq!(Ok(b1.clone()), Block::from_json(&json));
+        let json = chain.to_json().replace(&format!("{:02x}", b1.hash()), &upper(&b1.hash()));
+        assert_eq!(chain.blocks(), Blockchain::from_json(&json).unwrap().blocks());
+
+        // still not signs or other characters from_str_radix would take
+        let hash = format!("{:02x}", b1.hash());
+        let signed = format!("+{}", &hash[1..]);
+        let json = b1.to_json().replace(&hash, &signed);
+        assert_eq!(Err(JsonError::BadField { block: None, field: "hash", value: signed }), Block::from_json(&json));
+    }
+
+    // Test that chains survive a round trip with their parameters
+    #[test]
+    fn chain_round_trip() {
+        let chain = sample_chain();
+        let imported = Blockchain::from_json(&chain.to_json()).unwrap();
+        assert_eq!(chain.blocks(), imported.blocks());
+        assert_eq!(chain.params(), imported.params());
+
+        let clock = Arc::new(ManualClock::new(1_700_000_000));
+        let params = ChainParams {
+            pow_rule: PowRuleKind::Target,
+            timestamps: true,
+            retarget: Retarget::MovingAverage { window: 3, spacing: 60 },
+            ..ChainParams::default()
+        };
+        let mut genesis = Block::initial(8);
+        genesis.set_pow_rule(PowRuleKind::Target);
+        genesis.set_timestamp(clock.now());
+        genesis.mine(1);
+        let mut chain = Blockchain::with_clock(params, genesis, clock.clone()).unwrap();
+        for _ in 0..4 {
+            clock.advance(30);
+            chain.mine_next(String::from("retargeted"), 1);
+        }
+        let json = chain.to_json();
+        assert!(json.contains("\"kind\": \"moving-average\""));
+        assert_eq!(Ok(params), ChainParams::from_json(&params.to_json()));
+        let imported = Blockchain::from_json(&json).unwrap();
+        assert_eq!(chain.blocks(), imported.blocks());
+        assert_eq!(chain.params(), imported.params());
+    }
+
+    // Test that tampered files are rejected, saying which block and why
+    #[test]
+    fn tampered_files() {
+        let chain = sample_chain();
+        let json = chain.to_json();
+        let b1 = chain.get(1).unwrap();
+
+        // changed data: the stated hash no longer matches
+        let tampered = json.replace("\"first\"", "\"forged\"");
+        assert!(matches!(Blockchain::from_json(&tampered), Err(JsonError::HashMismatch { block: Some(1), .. })));
+
+        // changed data and hash: the old proof doesn't work for the new data
+        let mut forged = Block::next(chain.genesis(), String::from("forged"));
+        forged.set_proof(b1.proof().unwrap());
+        let tampered = json.replace(&indent(&b1.to_json()), &indent(&forged.to_json()));
+        assert_ne!(json, tampered);
+        assert_eq!(
+            Err(JsonError::Invalid {
+                block: Some(1),
+                error: ValidationError::InsufficientWork { difficulty: 8.into(), proof: b1.proof().unwrap() },
+            }),
+            Blockchain::from_json(&tampered).map(|_| ())
+        );
+
+        // bad field values
+        let tampered = json.replacen("\"sha256\"", "\"md5\"", 2);
+        assert!(matches!(
+            Blockchain::from_json(&tampered),
+            Err(JsonError::BadField { block: None, field: "hash_function", .. })
+        ));
+        let tampered = json.replacen(&format!("\"prev_hash\": \"{:0>64}\"", ""), "\"prev_hash\": \"00\"", 1);
+        assert_eq!(
+            Err(JsonError::BadField { block: Some(0), field: "prev_hash", value: String::from("00") }),
+            Blockchain::from_json(&tampered).map(|_| ())
+        );
+        let tampered = json.replacen("\"difficulty\": 8", "\"difficulty\": 300", 1);
+        assert!(matches!(Blockchain::from_json(&tampered), Err(JsonError::BadField { field: "difficulty", .. })));
+        let retargets = [
+            ("\"kind\": \"fixed-window\", \"interval\": 0, \"spacing\": 600", r#"{"kind":"fixed-window","interval":0,"spacing":600}"#),
+            ("\"kind\": \"moving-average\", \"window\": 18446744073709551615, \"spacing\": 600", r#"{"kind":"moving-average","window":18446744073709551615,"spacing":600}"#),
+        ];
+        for (retarget, value) in retargets {
+            let tampered = json.replacen("\"kind\": \"off\"", retarget, 1);
+            assert_ne!(json, tampered);
+            assert_eq!(
+                Err(JsonError::BadField { block: None, field: "retarget", value: String::from(value) }),
+                Blockchain::from_json(&tampered).map(|_| ())
+            );
+        }
+
+        // not JSON, or not a chain
+        assert!(matches!(Blockchain::from_json("{"), Err(JsonError::Syntax(_))));
+        assert!(matches!(Blockchain::from_json(&json.replace("\"data\"", "\"payload\"")), Err(JsonError::Syntax(_))));
+        match Block::from_json(&b1.to_json().replace("\"generation\"", "\"gen\"")) {
+            Err(JsonError::Syntax(message)) => assert!(message.contains("line"), "{}", message),
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+
+        // a lone block with a proof must have done the work
+        let mut unmined = Block::initial(20);
+        unmined.set_proof(1);
+        let json = unmined.to_json();
+        assert_eq!(
+            Err(JsonError::Invalid { block: None, error: ValidationError::InsufficientWork { difficulty: 20.into(), proof: 1 } }),
+            Block::from_json(&json)
+        );
+    }
+}
@@ -1,4 +1,31 @@
+// each *_tests.rs file wraps its tests in a module of the same name (module_inception)
 pub mod block;
+#[allow(clippy::module_inception, clippy::bool_assert_comparison)]
 mod block_tests;
+pub mod chain;
+#[allow(clippy::module_inception)]
+mod chain_tests;
+pub mod cli;
+#[allow(clippy::module_inception)]
+mod cli_tests;
+pub mod clock;
+#[allow(clippy::module_inception)]
+mod clock_tests;
+pub mod encoding;
+#[allow(clippy::module_inception)]
+mod encoding_tests;
+pub mod hasher;
+#[allow(clippy::module_inception)]
+mod hasher_tests;
+pub mod json;
+#[allow(clippy::module_inception)]
+mod json_tests;
+pub mod pow;
+#[allow(clippy::module_inception)]
+mod pow_tests;
 pub mod queue;
+#[allow(clippy::module_inception)]
 mod queue_tests;
+pub mod store;
+#[allow(clippy::module_inception)]
+mod store_tests;
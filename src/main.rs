@@ -1,4 +1,13 @@
+use a3::cli::{self, CliError};
+
 fn main() {
-    // Nothing is required here, but it may be useful for testing.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = cli::run(&args, &mut std::io::stdout().lock()) {
+        eprintln!("a3: {}", e);
+        if let CliError::Usage(_) = e {
+            eprintln!("{}", cli::USAGE);
+            std::process::exit(2);
+        }
+        std::process::exit(1);
+    }
 }
-
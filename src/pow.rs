@@ -0,0 +1,326 @@
+use crate::block::Hash;
+use std::fmt;
+
+// A number of hash bits that must be zero: anything from 0 up to the full width of the hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Difficulty(u16);
+
+impl Difficulty {
+    pub const MAX: Difficulty = Difficulty(256);
+
+    pub fn new(bits: u16) -> Result<Difficulty, DifficultyError> {
+        if bits > Difficulty::MAX.0 {
+            return Err(DifficultyError { bits });
+        }
+        Ok(Difficulty(bits))
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub fn expected_work(self) -> f64 {
+        // average number of hashes needed to find a proof: 2^bits
+        2f64.powi(self.0.into())
+    }
+}
+
+// every u8 is in range
+impl From<u8> for Difficulty {
+    fn from(bits: u8) -> Difficulty {
+        Difficulty(bits.into())
+    }
+}
+
+impl TryFrom<u16> for Difficulty {
+    type Error = DifficultyError;
+
+    fn try_from(bits: u16) -> Result<Difficulty, DifficultyError> {
+        Difficulty::new(bits)
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DifficultyError {
+    pub bits: u16,
+}
+
+impl fmt::Display for DifficultyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "difficulty {} is more than the {} bits in a hash", self.bits, Difficulty::MAX)
+    }
+}
+
+impl std::error::Error for DifficultyError {}
+
+// Decides whether a block hash is enough proof of work.
+pub trait PowRule: Clone + Send + Sync {
+    fn accepts(&self, hash: &Hash) -> bool;
+}
+
+// The hash's last `0` bits are zero. The original rule: bits are counted from the end of
+// the hash bytes, and from the least significant end of each byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrailingZeroBits(pub Difficulty);
+
+impl PowRule for TrailingZeroBits {
+    #[allow(clippy::needless_return)]
+    fn accepts(&self, hash: &Hash) -> bool {
+        let n_bytes: usize = (self.0.bits()/8).into();
+        let n_bits: usize = (self.0.bits()%8).into();
+
+        let last_byte_index = hash.len() - 1;
+        if n_bytes > 0 {
+            for i in 0..n_bytes {
+                if hash[last_byte_index - i] != 0u8{
+                    return false;
+                }
+            }
+        }
+
+        if n_bits == 0 {
+            return true; // also covers all 32 bytes being zero, where there is no next byte
+        }
+        let next_byte_from_end = hash.len() - 1 - n_bytes;
+        if hash[next_byte_from_end] as usize % (1<<n_bits) != 0 {
+            return false;
+        }
+        return true;
+    }
+}
+
+// The hash's first `0` bits are zero, as in Bitcoin's "leading zeros".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeadingZeroBits(pub Difficulty);
+
+impl PowRule for LeadingZeroBits {
+    fn accepts(&self, hash: &Hash) -> bool {
+        let n_bytes: usize = (self.0.bits()/8).into();
+        let n_bits = self.0.bits()%8;
+
+        if hash[..n_bytes].iter().any(|&b| b != 0) {
+            return false;
+        }
+        n_bits == 0 || hash[n_bytes] >> (8 - n_bits) == 0
+    }
+}
+
+// A 256-bit proof-of-work threshold, stored big-endian. Unlike a number of zero bits, a
+// target can make the expected work any amount, not just a power of two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Target([u8; 32]);
+
+impl Target {
+    pub const MAX: Target = Target([0xff; 32]);
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Target {
+        Target(bytes)
+    }
+
+    pub fn to_bytes(self) -> [u8; 32] {
+        self.0
+    }
+
+    pub fn from_difficulty(difficulty: impl Into<Difficulty>) -> Target {
+        // the largest 256-bit number whose first `difficulty` bits are zero: 2^(256 - difficulty) - 1
+        let difficulty = difficulty.into().bits();
+        let mut target = [0xffu8; 32];
+        let n_bytes: usize = (difficulty/8).into();
+        target[..n_bytes].fill(0);
+        if n_bytes < target.len() {
+            target[n_bytes] = 0xff >> (difficulty%8);
+        }
+        Target(target)
+    }
+
+    pub fn from_compact(bits: u32) -> Option<Target> {
+        // Decode Bitcoin's "nBits" format: mantissa * 256^(size - 3), where the top byte is
+        // the size and the low 23 bits the mantissa. Returns None for the negative and
+        // larger-than-256-bit values the format can also express.
+        let size = (bits >> 24) as usize;
+        let mantissa = bits & 0x007f_ffff;
+        if bits & 0x0080_0000 != 0 && mantissa != 0 {
+            return None;
+        }
+        let mut target = [0u8; 32];
+        for (k, &byte) in mantissa.to_be_bytes()[1..].iter().enumerate() {
+            // this byte's position counting from the least significant end
+            let Some(position) = size.checked_sub(k + 1) else {
+                continue; // shifted out when size < 3
+            };
+            if position >= target.len() {
+                if byte != 0 {
+                    return None;
+                }
+                continue;
+            }
+            target[target.len() - 1 - position] = byte;
+        }
+        Some(Target(target))
+    }
+
+    pub fn to_compact(self) -> u32 {
+        // Encode in "nBits" format, keeping the 3 most significant bytes (so rounding down).
+        let mut size = self.0.iter().skip_while(|&&b| b == 0).count();
+        let digit = |k: usize| (32 + k).checked_sub(size).and_then(|i| self.0.get(i)).copied().unwrap_or(0) as u32;
+        let mut mantissa = (digit(0) << 16) | (digit(1) << 8) | digit(2);
+        if mantissa & 0x0080_0000 != 0 {
+            // that bit means "negative", so use one more byte of size instead
+            mantissa >>= 8;
+            size += 1;
+        }
+        ((size as u32) << 24) | mantissa
+    }
+
+    pub fn mul_div(self, num: u64, den: u64) -> Target {
+        // self * num / den, rounded down, saturating at Target::MAX
+        assert!(den != 0, "Target::mul_div by zero");
+        // little-endian 64-bit limbs, with one more for the product's overflow
+        let mut limbs = [0u64; 5];
+        for (i, chunk) in self.0.rchunks(8).enumerate() {
+            limbs[i] = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+        let mut carry = 0u128;
+        for limb in limbs.iter_mut() {
+            let product = *limb as u128 * num as u128 + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+        let mut remainder = 0u128;
+        for limb in limbs.iter_mut().rev() {
+            let dividend = (remainder << 64) | *limb as u128;
+            *limb = (dividend / den as u128) as u64;
+            remainder = dividend % den as u128;
+        }
+        if limbs[4] != 0 {
+            return Target::MAX;
+        }
+        let mut target = [0u8; 32];
+        for (i, chunk) in target.rchunks_mut(8).enumerate() {
+            chunk.copy_from_slice(&limbs[i].to_be_bytes());
+        }
+        Target(target)
+    }
+
+    pub fn saturating_add(self, other: Target) -> Target {
+        let mut target = [0u8; 32];
+        let mut carry = 0u16;
+        for i in (0..32).rev() {
+            let sum = self.0[i] as u16 + other.0[i] as u16 + carry;
+            target[i] = sum as u8;
+            carry = sum >> 8;
+        }
+        if carry != 0 {
+            return Target::MAX;
+        }
+        Target(target)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0.iter().fold(0.0, |acc, &b| acc * 256.0 + b as f64)
+    }
+
+    pub fn expected_work(self) -> f64 {
+        // average number of hashes needed to find one at or below this target: 2^256 / (target + 1)
+        2f64.powi(256) / (self.to_f64() + 1.0)
+    }
+
+    pub fn difficulty_bits(self) -> f64 {
+        // the (fractional) number of zero bits with the same expected work
+        self.expected_work().log2()
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+// The hash, read as a 256-bit big-endian integer, is at most the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BelowTarget(pub Target);
+
+impl PowRule for BelowTarget {
+    fn accepts(&self, hash: &Hash) -> bool {
+        // big-endian byte arrays compare the same way as the numbers they encode
+        hash.as_slice() <= &self.0.0[..]
+    }
+}
+
+// Which PowRule a block (and a chain) uses. Each rule needs about 2^difficulty hashes
+// per proof on average, whichever one is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PowRuleKind {
+    #[default]
+    TrailingZeroBits,
+    LeadingZeroBits,
+    Target,
+}
+
+impl PowRuleKind {
+    pub const ALL: [PowRuleKind; 3] = [PowRuleKind::TrailingZeroBits, PowRuleKind::LeadingZeroBits, PowRuleKind::Target];
+
+    pub fn rule(self, difficulty: impl Into<Difficulty>) -> AnyPowRule {
+        let difficulty = difficulty.into();
+        match self {
+            PowRuleKind::TrailingZeroBits => AnyPowRule::TrailingZeroBits(TrailingZeroBits(difficulty)),
+            PowRuleKind::LeadingZeroBits => AnyPowRule::LeadingZeroBits(LeadingZeroBits(difficulty)),
+            PowRuleKind::Target => AnyPowRule::Target(BelowTarget(Target::from_difficulty(difficulty))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PowRuleKind::TrailingZeroBits => "trailing-zeros",
+            PowRuleKind::LeadingZeroBits => "leading-zeros",
+            PowRuleKind::Target => "target",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PowRuleKind> {
+        PowRuleKind::ALL.into_iter().find(|k| k.name() == name)
+    }
+
+    pub fn id(self) -> u8 {
+        // how the binary block encoding identifies it: the position in ALL
+        PowRuleKind::ALL.iter().position(|&k| k == self).unwrap() as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<PowRuleKind> {
+        PowRuleKind::ALL.get(usize::from(id)).copied()
+    }
+}
+
+impl fmt::Display for PowRuleKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Any of the rules above, as chosen by a PowRuleKind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnyPowRule {
+    TrailingZeroBits(TrailingZeroBits),
+    LeadingZeroBits(LeadingZeroBits),
+    Target(BelowTarget),
+}
+
+impl PowRule for AnyPowRule {
+    fn accepts(&self, hash: &Hash) -> bool {
+        match self {
+            AnyPowRule::TrailingZeroBits(r) => r.accepts(hash),
+            AnyPowRule::LeadingZeroBits(r) => r.accepts(hash),
+            AnyPowRule::Target(r) => r.accepts(hash),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+#[cfg(test)]
+mod pow_tests {
+    use crate::block::{Block, Hash, MiningOptions, ValidationError};
+    use crate::chain::{Blockchain, ChainParams};
+    use crate::hasher::HashFunction;
+    use crate::pow::{BelowTarget, Difficulty, DifficultyError, LeadingZeroBits, PowRule, PowRuleKind, Target, TrailingZeroBits};
+
+    fn hash_with(first: u8, last: u8) -> Hash {
+        let mut bytes = [0x55u8; 32];
+        bytes[0] = first;
+        bytes[31] = last;
+        Hash::from(bytes)
+    }
+
+    // Test each rule on hashes with a known number of zero bits at either end
+    #[test]
+    fn rules_count_zero_bits() {
+        let h = hash_with(0x0f, 0xf0); // 4 leading and 4 trailing zero bits
+        assert!(TrailingZeroBits(4.into()).accepts(&h));
+        assert!(!TrailingZeroBits(5.into()).accepts(&h));
+        assert!(LeadingZeroBits(4.into()).accepts(&h));
+        assert!(!LeadingZeroBits(5.into()).accepts(&h));
+        assert!(BelowTarget(Target::from_difficulty(4)).accepts(&h));
+        assert!(!BelowTarget(Target::from_difficulty(5)).accepts(&h));
+
+        let zero = Hash::default();
+        for d in [0, 1, 8, 9, 255] {
+            assert!(TrailingZeroBits(d.into()).accepts(&zero));
+            assert!(LeadingZeroBits(d.into()).accepts(&zero));
+            assert!(BelowTarget(Target::from_difficulty(d)).accepts(&zero));
+        }
+        assert!(LeadingZeroBits(0.into()).accepts(&Hash::from([0xffu8; 32])));
+
+        // the target is compared as a number, not bit by bit
+        let target = BelowTarget(Target::from_bytes(hash_with(0x12, 0x00).into()));
+        assert!(target.accepts(&hash_with(0x12, 0x00)));
+        assert!(target.accepts(&hash_with(0x11, 0xff)));
+        assert!(!target.accepts(&hash_with(0x12, 0x01)));
+        assert!(!target.accepts(&hash_with(0x13, 0x00)));
+        assert_eq!(0x00, Target::from_difficulty(8).to_bytes()[0]);
+        assert_eq!(0x3f, Target::from_difficulty(10).to_bytes()[1]);
+        assert_eq!(0x01, Target::from_difficulty(255).to_bytes()[31]);
+        assert_eq!(Target::MAX, Target::from_difficulty(0));
+    }
+
+    // Test that difficulties are checked, and that every rule works up to the full hash width
+    #[test]
+    fn difficulty_range() {
+        assert_eq!(256, Difficulty::MAX.bits());
+        assert_eq!(Ok(Difficulty::MAX), Difficulty::new(256));
+        assert_eq!(Err(DifficultyError { bits: 257 }), Difficulty::new(257));
+        assert_eq!(Err(DifficultyError { bits: u16::MAX }), Difficulty::try_from(u16::MAX));
+        assert_eq!(Difficulty::new(255).unwrap(), Difficulty::from(255u8));
+
+        let zero = Hash::default();
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        let one = Hash::from(one);
+        for kind in PowRuleKind::ALL {
+            assert!(kind.rule(Difficulty::MAX).accepts(&zero));
+            assert!(!kind.rule(Difficulty::MAX).accepts(&hash_with(0x80, 0x01)));
+        }
+        assert!(!TrailingZeroBits(Difficulty::MAX).accepts(&one));
+        assert!(!BelowTarget(Target::from_difficulty(Difficulty::MAX)).accepts(&one));
+        assert!(LeadingZeroBits(Difficulty::new(255).unwrap()).accepts(&one));
+        assert_eq!(Target::from_bytes([0; 32]), Target::from_difficulty(Difficulty::MAX));
+
+        // hard blocks can still be created, hashed and (unsuccessfully) validated
+        let mut block = Block::initial_legacy(0);
+        block.set_difficulty(Difficulty::MAX);
+        block.set_proof(0);
+        assert_eq!(format!("{:0>64}:0:256::0", ""), block.hash_string());
+        assert_eq!(Err(ValidationError::InsufficientWork { difficulty: Difficulty::MAX, proof: 0 }), block.validate());
+    }
+
+    fn target_hex(hex: &str) -> Target {
+        let mut bytes = [0u8; 32];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        Target::from_bytes(bytes)
+    }
+
+    // Test the compact ("nBits") target encoding against values from Bitcoin
+    #[test]
+    fn compact_targets() {
+        let genesis = target_hex("00000000ffff0000000000000000000000000000000000000000000000000000");
+        assert_eq!(Some(genesis), Target::from_compact(0x1d00ffff));
+        assert_eq!(0x1d00ffff, genesis.to_compact());
+
+        let block_100k = target_hex("000000000004864c000000000000000000000000000000000000000000000000");
+        assert_eq!(Some(block_100k), Target::from_compact(0x1b04864c));
+        assert_eq!(0x1b04864c, block_100k.to_compact());
+
+        // small values, where the mantissa is shifted right
+        assert_eq!(Some(target_hex(&format!("{:0>64}", "12"))), Target::from_compact(0x01123456));
+        assert_eq!(Some(target_hex(&format!("{:0>64}", "1234"))), Target::from_compact(0x02123456));
+        assert_eq!(0x01120000, target_hex(&format!("{:0>64}", "12")).to_compact());
+        assert_eq!(Some(Target::from_bytes([0; 32])), Target::from_compact(0));
+        assert_eq!(0, Target::from_bytes([0; 32]).to_compact());
+
+        // a high mantissa bit would read as negative, so it moves into the size instead
+        let t = target_hex(&format!("{:0>64}", "80"));
+        assert_eq!(0x02008000, t.to_compact());
+        assert_eq!(Some(t), Target::from_compact(0x02008000));
+
+        // negative and overflowing encodings
+        assert_eq!(None, Target::from_compact(0x04923456));
+        assert_eq!(None, Target::from_compact(0x21010000));
+        assert!(Target::from_compact(0x207fffff).is_some());
+
+        // encoding keeps the top 3 bytes, rounding down
+        for d in 0..=255 {
+            let t = Target::from_difficulty(d);
+            let rounded = Target::from_compact(t.to_compact()).unwrap();
+            assert!(rounded <= t);
+            assert_eq!(rounded.to_compact(), t.to_compact());
+        }
+    }
+
+    // Test that targets express work between the powers of two a bit count allows
+    #[test]
+    fn fractional_targets() {
+        assert!((Target::from_difficulty(10).expected_work() - 1024.0).abs() < 1e-6);
+        assert!((Target::from_difficulty(10).difficulty_bits() - 10.0).abs() < 1e-9);
+
+        // 1.5 times the work of 10 zero bits
+        let target = target_hex("002aaa0000000000000000000000000000000000000000000000000000000000");
+        assert!((target.difficulty_bits() - 1536f64.log2()).abs() < 0.01);
+        assert!(Target::from_difficulty(11) < target && target < Target::from_difficulty(10));
+
+        let mut b0 = Block::initial_legacy(10);
+        b0.set_target(target);
+        assert_eq!(PowRuleKind::Target, b0.pow_rule_kind());
+        assert_eq!(Some(target), b0.target());
+        b0.mine(2);
+        assert!(b0.hash().as_slice() <= &target.to_bytes()[..]);
+        assert!(b0.is_valid());
+        // the target is part of what gets hashed
+        assert_eq!(
+            format!("{:0>64}:0:10:{}::{}", "", 0x1f2aaa00u32, b0.proof().unwrap()),
+            b0.hash_string()
+        );
+
+        let mut b1 = Block::next(&b0, String::from("same target"));
+        b1.mine(2);
+        assert_eq!(Ok(()), b1.validate_next(&b0));
+        b1.set_target(Target::from_difficulty(12));
+        b1.mine(2);
+        assert_eq!(
+            Err(ValidationError::TargetMismatch { expected: Some(target), found: b1.target() }),
+            b1.validate_next(&b0)
+        );
+    }
+
+    // Test scaling targets, as retargeting does
+    #[test]
+    fn target_arithmetic() {
+        let t = Target::from_difficulty(10);
+        assert_eq!(Target::from_difficulty(12), t.mul_div(1, 4));
+        assert_eq!(t, t.mul_div(3, 3));
+        assert_eq!(t, Target::from_difficulty(12).mul_div(4, 1).saturating_add(target_hex(&format!("{:0>64}", "3"))));
+        assert_eq!(Target::MAX, t.mul_div(1 << 11, 1));
+        assert_eq!(Target::MAX, Target::MAX.saturating_add(target_hex(&format!("{:0>64}", "1"))));
+        assert_eq!(Target::MAX, Target::MAX.mul_div(u64::MAX, u64::MAX));
+
+        // carries across limbs, checked against u128 arithmetic on the low bytes
+        let small = target_hex(&format!("{:0>64}", "ffffffffffffffffff"));
+        let product = 0xff_ffff_ffff_ffff_ffffu128 * 1000 / 7;
+        assert_eq!(target_hex(&format!("{:0>64x}", product)), small.mul_div(1000, 7));
+        assert!((t.mul_div(3, 2).expected_work() - 1024.0 / 1.5).abs() < 1e-6);
+    }
+
+    // Test that the original rule still accepts the proofs from block_tests
+    #[test]
+    fn trailing_zero_bits_is_the_original_rule() {
+        let mut b0 = Block::initial_legacy(19);
+        assert_eq!(PowRuleKind::TrailingZeroBits, b0.pow_rule_kind());
+        b0.set_proof(87745);
+        assert!(TrailingZeroBits(19.into()).accepts(&b0.hash()));
+        assert!(b0.is_valid());
+    }
+
+    // Test that every rule accepts about 1 in 2^difficulty hashes, so needs the same expected work
+    #[test]
+    fn rules_need_the_same_work() {
+        let n: u32 = 1 << 14;
+        let hashes: Vec<Hash> = (0..n).map(|i| HashFunction::Sha256.hash(&i.to_le_bytes())).collect();
+        for difficulty in [2, 5, 7] {
+            let expected = (n >> difficulty) as f64;
+            let counts: Vec<usize> = PowRuleKind::ALL
+                .iter()
+                .map(|kind| hashes.iter().filter(|h| kind.rule(difficulty).accepts(h)).count())
+                .collect();
+            for (kind, &count) in PowRuleKind::ALL.iter().zip(&counts) {
+                assert!(
+                    (count as f64 - expected).abs() < 0.2 * expected,
+                    "{} accepted {} hashes at difficulty {}, expected about {}",
+                    kind, count, difficulty, expected
+                );
+            }
+            // "at most 2^(256-d) - 1" and "d leading zero bits" are the same condition
+            assert_eq!(counts[1], counts[2]);
+        }
+    }
+
+    // Test mining and validating blocks under each rule
+    #[test]
+    fn mine_with_each_rule() {
+        for kind in PowRuleKind::ALL {
+            assert_eq!(Some(kind), PowRuleKind::from_name(kind.name()));
+
+            let mut b0 = Block::initial(10);
+            b0.set_pow_rule(kind);
+            b0.mine(2);
+            assert!(kind.rule(10).accepts(&b0.hash()));
+            assert!(b0.is_valid());
+
+            let mut b1 = Block::next(&b0, String::from("same rule"));
+            b1.mine(2);
+            assert_eq!(Ok(()), b1.validate_next(&b0));
+
+            let other = PowRuleKind::ALL.into_iter().find(|&k| k != kind).unwrap();
+            b1.set_pow_rule(other);
+            b1.mine(2);
+            assert_eq!(Err(ValidationError::PowRuleMismatch { expected: kind, found: other }), b1.validate_next(&b0));
+        }
+
+        // mining for a rule other than the block's own
+        let block = Block::initial(10);
+        let p = block.mine_range_with_rule(LeadingZeroBits(10.into()), 2, 0, 1 << 16, 64, MiningOptions::default()).unwrap();
+        assert!(LeadingZeroBits(10.into()).accepts(&block.hash_for_proof(p)));
+    }
+
+    // Test that a chain only accepts blocks using the rule in its parameters
+    #[test]
+    fn chain_enforces_pow_rule() {
+        let params = ChainParams { pow_rule: PowRuleKind::Target, ..ChainParams::default() };
+        let mut chain = Blockchain::mine_genesis_with(params, 8, 1);
+        chain.mine_next(String::from("target"), 1);
+        assert_eq!(PowRuleKind::Target, chain.tip().pow_rule_kind());
+
+        let mut wrong = Block::next(chain.tip(), String::from("leading zeros"));
+        wrong.set_pow_rule(PowRuleKind::LeadingZeroBits);
+        wrong.mine(1);
+        assert_eq!(
+            Err(ValidationError::PowRuleMismatch { expected: PowRuleKind::Target, found: PowRuleKind::LeadingZeroBits }),
+            chain.push(wrong)
+        );
+    }
+}
@@ -1,126 +1,611 @@
-use std::sync::mpsc;
+use std::any::Any;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 pub trait Task {
     type Output: Send;
     fn run(&self) -> Option<Self::Output>;
 }
 
+// Identifies an enqueued task; tasks are numbered from 0 in the order they were enqueued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    pub fn number(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task {}", self.0)
+    }
+}
+
+// A task whose run panicked. The worker running it is replaced, and the queue carries on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskPanic {
+    pub task: TaskId,
+    pub message: String, // the panic message, if it was a string
+}
+
+impl fmt::Display for TaskPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} panicked: {}", self.task, self.message)
+    }
+}
+
+impl std::error::Error for TaskPanic {}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => String::from("(not a string)"),
+        },
+    }
+}
+
+pub type TaskResult<Output> = Result<Output, TaskPanic>;
+
+// Limits on memory use. Like a pair of sync_channels, a queue with both capacities set can
+// deadlock a thread that only enqueues: once the outputs fill up, no task runs until some are
+// received, so enqueue waits forever for room. Such a thread has to use try_enqueue, receiving
+// when the queue is full, or receive between enqueues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueueOptions {
+    pub ordered: bool,                  // deliver outputs in the order their tasks were enqueued, not as they finish
+    pub task_capacity: Option<usize>,   // tasks waiting for a worker; enqueue blocks and try_enqueue fails beyond this
+    pub output_capacity: Option<usize>, // outputs held until received, counting tasks running; workers wait beyond this
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueOptionsError {
+    ZeroCapacity, // nothing could ever be enqueued, or run
+}
+
+impl fmt::Display for QueueOptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueOptionsError::ZeroCapacity => write!(f, "a queue capacity must be at least 1"),
+        }
+    }
+}
+
+impl std::error::Error for QueueOptionsError {}
+
+// Every task reports back once, with its output if it had one, so ordered delivery knows
+// when a task produced nothing and can move on.
+type Message<Output> = (TaskId, Option<TaskResult<Output>>);
+
+// Shared with the workers, which start their own replacements.
+type Workers = Arc<Mutex<Vec<thread::JoinHandle<()>>>>;
+
+// How many tasks are waiting for a worker, so enqueue can wait for room.
+#[derive(Default)]
+struct QueuedTasks {
+    count: Mutex<usize>,
+    taken: Condvar, // a worker took a task
+}
+
+impl QueuedTasks {
+    fn take(&self) {
+        *self.count.lock().unwrap() -= 1;
+        self.taken.notify_one();
+    }
+}
+
+// Room for outputs, with an output_capacity: a worker waits for it before running a task, so
+// no more outputs than that are ever held. Unordered, it counts outputs held and tasks running.
+// Ordered, a task may only run if it comes less than the capacity after the first task whose
+// output hasn't been received, so the task holding up delivery can always run.
+struct OutputRoom {
+    capacity: Option<usize>,
+    ordered: bool,
+    state: Mutex<RoomState>,
+    freed: Condvar,
+}
+
+#[derive(Default)]
+struct RoomState {
+    used: usize,     // unordered: outputs held, and tasks running
+    first_held: u64, // ordered: the first task whose output hasn't been received
+    closed: bool,    // shut down: tasks still waiting for room are discarded
+}
+
+impl OutputRoom {
+    fn new(options: &QueueOptions) -> OutputRoom {
+        OutputRoom {
+            capacity: options.output_capacity,
+            ordered: options.ordered,
+            state: Mutex::default(),
+            freed: Condvar::new(),
+        }
+    }
+
+    fn enter(&self, id: TaskId) -> bool {
+        // wait for room to run task `id`; false if the queue was shut down first
+        let Some(capacity) = self.capacity else {
+            return true;
+        };
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.closed {
+                return false;
+            }
+            if self.ordered {
+                if id.0 < state.first_held.saturating_add(capacity as u64) {
+                    return true;
+                }
+            } else if state.used < capacity {
+                state.used += 1;
+                return true;
+            }
+            state = self.freed.wait(state).unwrap();
+        }
+    }
+
+    fn leave(&self) {
+        // unordered: an output was received, or a task finished without one
+        if self.capacity.is_some() && !self.ordered {
+            self.state.lock().unwrap().used -= 1;
+            self.freed.notify_one();
+        }
+    }
+
+    fn advance(&self, first_held: u64) {
+        // ordered: outputs before `first_held` have been received, or never will be
+        if self.capacity.is_some() && self.ordered {
+            self.state.lock().unwrap().first_held = first_held;
+            self.freed.notify_all();
+        }
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.freed.notify_all();
+    }
+}
+
+// What a worker thread needs; each one gets a clone.
+struct Worker<TaskType: 'static + Task + Send> {
+    recv_tasks: spmc::Receiver<(TaskId, TaskType)>,
+    send_output: mpsc::Sender<Message<TaskType::Output>>,
+    queued: Arc<QueuedTasks>,
+    room: Arc<OutputRoom>,
+    workers: Workers,
+}
+
+impl<TaskType: 'static + Task + Send> Clone for Worker<TaskType> {
+    fn clone(&self) -> Self {
+        Worker {
+            recv_tasks: self.recv_tasks.clone(),
+            send_output: self.send_output.clone(),
+            queued: self.queued.clone(),
+            room: self.room.clone(),
+            workers: self.workers.clone(),
+        }
+    }
+}
+
+impl<TaskType: 'static + Task + Send> Worker<TaskType> {
+    fn spawn(self, predecessor: Option<thread::JoinHandle<()>>) {
+        // `predecessor` is a worker this one replaces, joined once this one is done
+        let workers = self.workers.clone();
+        // locked first, so the handle is listed before the thread could look for it
+        let mut workers = workers.lock().unwrap();
+        let worker = thread::spawn(move || {
+            self.run();
+            if let Some(predecessor) = predecessor {
+                predecessor.join().unwrap();
+            }
+        });
+        workers.push(worker);
+    }
+
+    fn run(self) {
+        // TODO: the main logic for a worker thread
+        loop {
+            let task_result = self.recv_tasks.recv();
+            // task_result will be Err() if the spmc::Sender has been destroyed and no more messages can be received here]
+            match task_result {
+                Err(_) => {
+                    //thread exits
+                    return;
+                }
+                Ok((id, r)) => {
+                    self.queued.take();
+                    if !self.room.enter(id) {
+                        // shut down while waiting for room: discarded, like the tasks still queued
+                        let _ = self.send_output.send((id, None));
+                        continue;
+                    }
+                    match panic::catch_unwind(AssertUnwindSafe(|| r.run())) {
+                        Ok(output) => {
+                            // report even a task without output (None)
+                            if output.is_none() {
+                                self.room.leave();
+                            }
+                            let _ = self.send_output.send((id, output.map(Ok)));
+                        }
+                        Err(payload) => {
+                            // the panic may have left this thread in a bad state: start a fresh
+                            // worker in its place before reporting it, handing it this thread's
+                            // handle so the list only holds running workers
+                            let me = thread::current().id();
+                            let handle = {
+                                let mut workers = self.workers.lock().unwrap();
+                                let position = workers.iter().position(|w| w.thread().id() == me);
+                                position.map(|i| workers.swap_remove(i))
+                            };
+                            self.clone().spawn(handle);
+                            let _ = self.send_output.send((id, Some(Err(TaskPanic { task: id, message: panic_message(payload) }))));
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 pub struct WorkQueue<TaskType: 'static + Task + Send> {
-    send_tasks: Option<spmc::Sender<TaskType>>, // Option because it will be set to None to close the queue
-    recv_tasks: spmc::Receiver<TaskType>,
+    send_tasks: Option<spmc::Sender<(TaskId, TaskType)>>, // Option because it will be set to None to close the queue
+    recv_tasks: spmc::Receiver<(TaskId, TaskType)>,
     //send_output: mpsc::Sender<TaskType::Output>, // not need in the struct: each worker will have its own clone.
-    recv_output: mpsc::Receiver<TaskType::Output>,
-    workers: Vec<thread::JoinHandle<()>>,
+    recv_output: mpsc::Receiver<Message<TaskType::Output>>,
+    workers: Workers,
+    queued: Arc<QueuedTasks>,
+    room: Arc<OutputRoom>,
+    options: QueueOptions,
+    next_id: u64,                                         // given to the next enqueued task
+    reported: u64,                                        // tasks that have finished (or were discarded)
+    ready: VecDeque<(TaskId, TaskResult<TaskType::Output>)>,        // outputs received but not yet handed out
+    next_delivery: u64,                                             // ordered: the task whose output is due next
+    reordered: BTreeMap<u64, Option<TaskResult<TaskType::Output>>>, // ordered: outputs that arrived early
+}
+
+// How long to wait for an output.
+#[derive(Clone, Copy)]
+enum Wait {
+    Forever,
+    No,
+    Until(Instant),
+}
+
+// Why no output was received.
+enum NoOutput {
+    Timeout,
+    Disconnected, // the workers have gone
+    Done,         // every task has finished
+}
+
+impl NoOutput {
+    fn try_recv_error(self) -> mpsc::TryRecvError {
+        match self {
+            NoOutput::Timeout => mpsc::TryRecvError::Empty,
+            NoOutput::Disconnected | NoOutput::Done => mpsc::TryRecvError::Disconnected,
+        }
+    }
+
+    fn recv_timeout_error(self) -> mpsc::RecvTimeoutError {
+        match self {
+            NoOutput::Timeout => mpsc::RecvTimeoutError::Timeout,
+            NoOutput::Disconnected | NoOutput::Done => mpsc::RecvTimeoutError::Disconnected,
+        }
+    }
+
+    fn panic(self) -> ! {
+        // for the receive methods that block until there's an output
+        match self {
+            NoOutput::Done => panic!("no output can come: every task has finished"),
+            _ => panic!("I have been shutdown incorrectly"),
+        }
+    }
 }
 
 impl<TaskType: 'static + Task + Send> WorkQueue<TaskType> {
     pub fn new(n_workers: usize) -> WorkQueue<TaskType> {
+        WorkQueue::with_options(n_workers, QueueOptions::default()).expect("the default options are valid")
+    }
+
+    pub fn with_options(n_workers: usize, options: QueueOptions) -> Result<WorkQueue<TaskType>, QueueOptionsError> {
         // create the channels; start the worker threads; record their JoinHandles
         // channels created for jobs going into the queue and the results coming out (work queue doesn't distinguish between results from the tasks
+        if options.task_capacity == Some(0) || options.output_capacity == Some(0) {
+            return Err(QueueOptionsError::ZeroCapacity);
+        }
         let (send_tasks, recv_tasks) = spmc::channel();
+        // unbounded, but the OutputRoom limits how many outputs can be in it
         let (mpsc_sender, recv_output) = mpsc::channel();
 
-        let mut workers = Vec::<thread::JoinHandle<()>>::new();
+        let worker = Worker {
+            recv_tasks: recv_tasks.clone(),
+            send_output: mpsc_sender,
+            queued: Arc::new(QueuedTasks::default()),
+            room: Arc::new(OutputRoom::new(&options)),
+            workers: Workers::default(),
+        };
         for _ in 0..n_workers {
-            let snd = mpsc_sender.clone();
-            let rcv = recv_tasks.clone();
-            workers.push(thread::spawn( move || {
-                Self::run(rcv, snd);
-            }));
+            worker.clone().spawn(None);
         }
 
-        WorkQueue::<TaskType> {
+        Ok(WorkQueue::<TaskType> {
             send_tasks: Some(send_tasks),
             recv_tasks,
             recv_output,
-            workers
+            workers: worker.workers,
+            queued: worker.queued,
+            room: worker.room,
+            options,
+            next_id: 0,
+            reported: 0,
+            ready: VecDeque::new(),
+            next_delivery: 0,
+            reordered: BTreeMap::new(),
+        })
+    }
+
+    pub fn enqueue(&mut self, t: TaskType) -> Result<TaskId, spmc::SendError<TaskType>> {
+        // send this task to a worker, waiting for room if the queue is full; fails if the queue has been shut down
+        if self.send_tasks.is_none() {
+            return Err(spmc::SendError(t));
         }
-        
+        let mut count = self.queued.count.lock().unwrap();
+        while self.options.task_capacity.is_some_and(|capacity| *count >= capacity) {
+            count = self.queued.taken.wait(count).unwrap();
+        }
+        *count += 1;
+        drop(count);
+        self.send(t).map_err(|t| {
+            *self.queued.count.lock().unwrap() -= 1;
+            spmc::SendError(t)
+        })
     }
 
-    fn run(recv_tasks: spmc::Receiver<TaskType>, send_output: mpsc::Sender<TaskType::Output>) {
-        // TODO: the main logic for a worker thread
-        loop {
-            let task_result = recv_tasks.recv();
-            // task_result will be Err() if the spmc::Sender has been destroyed and no more messages can be received here]
-            match task_result {
-                Err(_) => {
-                    //thread exits
-                    return;
-                }
-                Ok(r) => {
+    pub fn try_enqueue(&mut self, t: TaskType) -> Result<TaskId, mpsc::TrySendError<TaskType>> {
+        // like enqueue, but fails rather than waits if the queue is full
+        if self.send_tasks.is_none() {
+            return Err(mpsc::TrySendError::Disconnected(t));
+        }
+        let mut count = self.queued.count.lock().unwrap();
+        if self.options.task_capacity.is_some_and(|capacity| *count >= capacity) {
+            return Err(mpsc::TrySendError::Full(t));
+        }
+        *count += 1;
+        drop(count);
+        self.send(t).map_err(|t| {
+            *self.queued.count.lock().unwrap() -= 1;
+            mpsc::TrySendError::Disconnected(t)
+        })
+    }
 
-                    let output = r.run();
+    fn send(&mut self, t: TaskType) -> Result<TaskId, TaskType> {
+        // hand a task to the workers, once there's room for it
+        let snd = self.send_tasks.as_mut().expect("checked by the caller");
+        let id = TaskId(self.next_id);
+        snd.send((id, t)).map_err(|spmc::SendError((_, t))| t)?;
+        self.next_id += 1;
+        Ok(id)
+    }
 
-                    match output {
-                        Some(x) => {
-                            //solution found
-                            let _ = send_output.send(x);
-                        }
-                        None => {
-                            //No solution, do nothing
-                        }
-                    }
-                }
+    pub fn queued(&self) -> usize {
+        // how many tasks are waiting for a worker
+        *self.queued.count.lock().unwrap()
+    }
+
+    pub fn workers(&self) -> usize {
+        // how many worker threads there are: as many as the queue started with, until it's shut down
+        self.workers.lock().unwrap().len()
+    }
+
+    pub fn pending(&self) -> u64 {
+        // how many enqueued tasks haven't finished yet
+        self.next_id - self.reported
+    }
+
+    pub fn wait_idle(&mut self) -> bool {
+        // Block until every enqueued task has finished; their outputs can still be received afterwards.
+        // With an output_capacity, stop early once the outputs held fill it, since no task can run
+        // until some are received. Returns whether every task has finished.
+        while self.pending() > 0 && !self.outputs_full() {
+            match self.recv_output.recv() {
+                Ok((id, output)) => self.accept(id, output),
+                Err(_) => break,
             }
         }
+        self.pending() == 0
+    }
+
+    fn outputs_full(&self) -> bool {
+        let Some(capacity) = self.options.output_capacity else {
+            return false;
+        };
+        if self.options.ordered {
+            // every task allowed to run has reported
+            self.next_delivery >= self.first_held().saturating_add(capacity as u64)
+        } else {
+            self.ready.len() >= capacity
+        }
+    }
+
+    fn first_held(&self) -> u64 {
+        // ordered: the first task whose output hasn't been received (or that hasn't finished)
+        self.ready.front().map_or(self.next_delivery, |(id, _)| id.0)
     }
 
-    pub fn enqueue(&mut self, t: TaskType) -> Result<(), spmc::SendError<TaskType>> {
-        // send this task to a worker
-        match &mut self.send_tasks {
-            Some(snd) => {
-                snd.send(t)?;
-                Ok(())
+    fn accept(&mut self, id: TaskId, output: Option<TaskResult<TaskType::Output>>) {
+        // take in a finished task, making its output ready (in task order if the queue is ordered)
+        self.reported += 1;
+        if !self.options.ordered {
+            self.ready.extend(output.map(|output| (id, output)));
+            return;
+        }
+        self.reordered.insert(id.0, output);
+        while let Some(output) = self.reordered.remove(&self.next_delivery) {
+            self.ready.extend(output.map(|output| (TaskId(self.next_delivery), output)));
+            self.next_delivery += 1;
+        }
+        // tasks without output don't hold up the ones after them
+        self.room.advance(self.first_held());
+    }
+
+    fn next_output(&mut self, wait: Wait) -> Result<(TaskId, TaskResult<TaskType::Output>), NoOutput> {
+        // the next output or panic, skipping tasks that had neither
+        loop {
+            if let Some(output) = self.ready.pop_front() {
+                // received: make room for another task to run
+                self.room.leave();
+                self.room.advance(self.first_held());
+                return Ok(output);
             }
-            None => {
-                Ok(())
+            if self.pending() == 0 {
+                return Err(NoOutput::Done);
+            }
+            let (id, output) = match wait {
+                Wait::Forever => self.recv_output.recv().map_err(|_| NoOutput::Disconnected)?,
+                Wait::No => self.recv_output.try_recv().map_err(|e| match e {
+                    mpsc::TryRecvError::Empty => NoOutput::Timeout,
+                    mpsc::TryRecvError::Disconnected => NoOutput::Disconnected,
+                })?,
+                Wait::Until(deadline) => {
+                    self.recv_output.recv_timeout(deadline.saturating_duration_since(Instant::now())).map_err(|e| match e {
+                        mpsc::RecvTimeoutError::Timeout => NoOutput::Timeout,
+                        mpsc::RecvTimeoutError::Disconnected => NoOutput::Disconnected,
+                    })?
+                }
+            };
+            self.accept(id, output);
+        }
+    }
+
+    fn next_success(&mut self, wait: Wait) -> Result<(TaskId, TaskType::Output), NoOutput> {
+        // the next output, skipping tasks that panicked too
+        loop {
+            if let (id, Ok(output)) = self.next_output(wait)? {
+                return Ok((id, output));
             }
         }
     }
 
-    // Helper methods that let you receive results in various ways
-    pub fn iter(&mut self) -> mpsc::Iter<TaskType::Output> {
-        self.recv_output.iter()
+    // Helper methods that let you receive results in various ways. Tasks that panicked are
+    // skipped silently, as if they had no output: use the tagged methods below to find out
+    // about them. Once every task has finished, iterators end, recv_pending returns None and
+    // the others report Disconnected.
+    pub fn iter(&mut self) -> Iter<'_, TaskType> {
+        Iter { queue: self }
     }
     pub fn recv(&mut self) -> TaskType::Output {
-        self.recv_output
-            .recv()
-            .expect("I have been shutdown incorrectly")
+        match self.next_success(Wait::Forever) {
+            Ok((_, output)) => output,
+            Err(e) => e.panic(),
+        }
+    }
+    pub fn recv_pending(&mut self) -> Option<TaskType::Output> {
+        self.next_success(Wait::Forever).ok().map(|(_, output)| output)
     }
     pub fn try_recv(&mut self) -> Result<TaskType::Output, mpsc::TryRecvError> {
-        self.recv_output.try_recv()
+        self.next_success(Wait::No).map(|(_, output)| output).map_err(NoOutput::try_recv_error)
     }
     pub fn recv_timeout(
-        &self,
-        timeout: std::time::Duration,
+        &mut self,
+        timeout: Duration,
     ) -> Result<TaskType::Output, mpsc::RecvTimeoutError> {
-        self.recv_output.recv_timeout(timeout)
+        self.next_success(Wait::Until(Instant::now() + timeout))
+            .map(|(_, output)| output)
+            .map_err(NoOutput::recv_timeout_error)
     }
 
+    // The same, with the ID of the task each output came from, or a TaskPanic if it panicked
+    pub fn iter_tagged(&mut self) -> TaggedIter<'_, TaskType> {
+        TaggedIter { queue: self }
+    }
+    pub fn recv_tagged(&mut self) -> (TaskId, TaskResult<TaskType::Output>) {
+        self.next_output(Wait::Forever).unwrap_or_else(|e| e.panic())
+    }
+    pub fn recv_pending_tagged(&mut self) -> Option<(TaskId, TaskResult<TaskType::Output>)> {
+        self.next_output(Wait::Forever).ok()
+    }
+    pub fn try_recv_tagged(&mut self) -> Result<(TaskId, TaskResult<TaskType::Output>), mpsc::TryRecvError> {
+        self.next_output(Wait::No).map_err(NoOutput::try_recv_error)
+    }
+    pub fn recv_timeout_tagged(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<(TaskId, TaskResult<TaskType::Output>), mpsc::RecvTimeoutError> {
+        self.next_output(Wait::Until(Instant::now() + timeout)).map_err(NoOutput::recv_timeout_error)
+    }
+
+    #[allow(clippy::while_let_loop)]
     pub fn shutdown(&mut self) {
         // Destroy the spmc::Sender so everybody knows no more tasks are incoming;
         // drain any pending tasks in the queue; wait for each worker thread to finish.
         // HINT: Vec.drain(..)
         self.send_tasks = None;
+        self.room.close();
 
         loop {
             match self.recv_tasks.recv() {
-                Ok(_) => {
-                    //do nothing, discard
+                Ok((id, _)) => {
+                    //discard, but count the task as finished so nothing waits for it
+                    self.queued.take();
+                    self.accept(id, None);
                 }
                 Err(_) => {
                     break
                 }
             }
         }
-        for worker in self.workers.drain(..) {
-            worker.join().unwrap();
+        // take in what the workers report until they've all gone, so every task counts as finished
+        while let Ok((id, output)) = self.recv_output.recv() {
+            self.accept(id, output);
+        }
+        // workers replacing ones that panicked can join the list while we wait
+        loop {
+            let workers: Vec<_> = self.workers.lock().unwrap().drain(..).collect();
+            if workers.is_empty() {
+                break;
+            }
+            for worker in workers {
+                // panics are caught in the tasks, so the threads themselves finish cleanly; a
+                // replacement joins the worker it replaced
+                worker.join().unwrap();
+            }
         }
     }
 }
 
+// Outputs as they're received, until every task has finished. Skips tasks that panicked.
+pub struct Iter<'a, TaskType: 'static + Task + Send> {
+    queue: &'a mut WorkQueue<TaskType>,
+}
+
+impl<TaskType: 'static + Task + Send> Iterator for Iter<'_, TaskType> {
+    type Item = TaskType::Output;
+
+    fn next(&mut self) -> Option<TaskType::Output> {
+        self.queue.recv_pending()
+    }
+}
+
+pub struct TaggedIter<'a, TaskType: 'static + Task + Send> {
+    queue: &'a mut WorkQueue<TaskType>,
+}
+
+impl<TaskType: 'static + Task + Send> Iterator for TaggedIter<'_, TaskType> {
+    type Item = (TaskId, TaskResult<TaskType::Output>);
+
+    fn next(&mut self) -> Option<(TaskId, TaskResult<TaskType::Output>)> {
+        self.queue.recv_pending_tagged()
+    }
+}
+
 impl<TaskType: 'static + Task + Send> Drop for WorkQueue<TaskType> {
     fn drop(&mut self) {
         // "Finalisation in destructors" pattern: https://rust-unofficial.github.io/patterns/idioms/dtor-finally.html
@@ -1,7 +1,8 @@
 #[cfg(test)]
 mod queue_tests {
-    use crate::queue::{Task, WorkQueue};
+    use crate::queue::{QueueOptions, QueueOptionsError, Task, TaskId, TaskPanic, WorkQueue};
     use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
     use std::time::{Duration, Instant};
     use std::{sync, thread, time};
 
@@ -21,6 +22,69 @@ mod queue_tests {
         }
     }
 
+    // Sleeps for `delay_ms`, then returns its number unless told to return nothing.
+    struct NumberedTask {
+        number: u64,
+        delay_ms: u64,
+        output: bool,
+    }
+    impl Task for NumberedTask {
+        type Output = u64;
+        fn run(&self) -> Option<u64> {
+            thread::sleep(Duration::from_millis(self.delay_ms));
+            self.output.then_some(self.number)
+        }
+    }
+
+    // Panics when its number is odd.
+    struct FragileTask(u64);
+    impl Task for FragileTask {
+        type Output = u64;
+        fn run(&self) -> Option<u64> {
+            thread::sleep(DELAY / 2);
+            if self.0 % 2 == 1 {
+                panic!("odd task {}", self.0);
+            }
+            Some(self.0)
+        }
+    }
+
+    // Counts itself as started, then returns its number once its gate is sent to or dropped.
+    struct GatedTask {
+        number: u64,
+        started: sync::Arc<AtomicUsize>,
+        gate: mpsc::Receiver<()>,
+    }
+    impl Task for GatedTask {
+        type Output = u64;
+        fn run(&self) -> Option<u64> {
+            self.started.fetch_add(1, Ordering::SeqCst);
+            let _ = self.gate.recv();
+            Some(self.number)
+        }
+    }
+
+    fn gated_task(number: u64, started: &sync::Arc<AtomicUsize>) -> (GatedTask, mpsc::Sender<()>) {
+        let (open, gate) = mpsc::channel();
+        (GatedTask { number, started: started.clone(), gate }, open)
+    }
+
+    fn wait_for_starts(started: &AtomicUsize, n: usize) {
+        while started.load(Ordering::SeqCst) < n {
+            thread::yield_now();
+        }
+    }
+
+    fn numbered_tasks(q: &mut WorkQueue<NumberedTask>, n_tasks: u64) -> Vec<TaskId> {
+        // later tasks finish first; every third task has no output
+        (0..n_tasks)
+            .map(|number| {
+                let task = NumberedTask { number, delay_ms: 10 * (n_tasks - number), output: number % 3 != 1 };
+                q.enqueue(task).unwrap()
+            })
+            .collect()
+    }
+
     #[test]
     // Test that the work queue can do jobs and get correct results back.
     fn basics() {
@@ -165,4 +229,230 @@ mod queue_tests {
             "work continued after .shutdown(): threads were leaked because they weren't joined"
         );
     }
+
+    #[test]
+    // Test that outputs come with the ID of the task that produced them
+    fn tagged() {
+        let mut q = WorkQueue::<NumberedTask>::new(4);
+        let ids = numbered_tasks(&mut q, 8);
+        assert_eq!((0..8).collect::<Vec<_>>(), ids.iter().map(|id| id.number()).collect::<Vec<_>>());
+
+        let mut outputs: Vec<(TaskId, u64)> = (0..5).map(|_| q.recv_tagged()).map(|(id, output)| (id, output.unwrap())).collect();
+        for (id, output) in &outputs {
+            assert_eq!(id.number(), *output);
+        }
+        outputs.sort();
+        assert_eq!(vec![0, 2, 3, 5, 6], outputs.iter().map(|(_, output)| *output).collect::<Vec<_>>());
+        assert!(q.recv_timeout_tagged(3 * DELAY).is_err());
+
+        q.shutdown();
+        assert!(q.enqueue(NumberedTask { number: 9, delay_ms: 0, output: true }).is_err());
+    }
+
+    #[test]
+    // Test that an ordered queue delivers outputs in the order their tasks were enqueued
+    fn ordered() {
+        let mut q = WorkQueue::<NumberedTask>::with_options(4, QueueOptions { ordered: true, ..QueueOptions::default() }).unwrap();
+        numbered_tasks(&mut q, 8);
+        let outputs: Vec<(TaskId, u64)> = (0..5).map(|_| q.recv_tagged()).map(|(id, output)| (id, output.unwrap())).collect();
+        assert_eq!(vec![0, 2, 3, 5, 6], outputs.iter().map(|(_, output)| *output).collect::<Vec<_>>());
+        assert!(outputs.iter().all(|(id, output)| id.number() == *output));
+
+        // IDs carry on from the first batch
+        let ids = numbered_tasks(&mut q, 4);
+        assert_eq!(8, ids[0].number());
+        assert_eq!(0, q.recv());
+        assert_eq!(Ok(2), q.recv_timeout(DELAY));
+        assert_eq!(Ok(3), q.recv_timeout(DELAY));
+        assert!(q.try_recv().is_err());
+    }
+
+    #[test]
+    // Test that the queue knows when every task has finished, even those without output
+    fn completion() {
+        let mut q = WorkQueue::<NumberedTask>::new(4);
+        assert_eq!(None, q.recv_pending());
+        numbered_tasks(&mut q, 8);
+        assert_eq!(8, q.pending());
+        let mut outputs: Vec<u64> = q.iter().collect();
+        outputs.sort();
+        assert_eq!(vec![0, 2, 3, 5, 6], outputs);
+        assert_eq!(0, q.pending());
+        assert_eq!(None, q.recv_pending());
+        assert_eq!(Err(mpsc::RecvTimeoutError::Disconnected), q.recv_timeout(DELAY));
+
+        // wait_idle keeps the outputs for later
+        numbered_tasks(&mut q, 4);
+        q.wait_idle();
+        assert_eq!(0, q.pending());
+        let mut outputs: Vec<u64> = q.iter_tagged().map(|(_, output)| output.unwrap()).collect();
+        outputs.sort();
+        assert_eq!(vec![0, 2, 3], outputs);
+        assert_eq!(Err(mpsc::TryRecvError::Disconnected), q.try_recv());
+
+        // tasks discarded by shutdown count as finished
+        let mut q = WorkQueue::<NumberedTask>::with_options(1, QueueOptions { ordered: true, ..QueueOptions::default() }).unwrap();
+        numbered_tasks(&mut q, 4);
+        thread::sleep(Duration::from_millis(10));
+        q.shutdown();
+        assert_eq!(0, q.pending());
+        assert_eq!(vec![0], q.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    // Test that a panicking task is reported against its ID, and its worker replaced
+    fn panics() {
+        let n_threads: u64 = 2;
+        let mut q = WorkQueue::<FragileTask>::with_options(n_threads as usize, QueueOptions { ordered: true, ..QueueOptions::default() }).unwrap();
+        for n in 0..8 {
+            q.enqueue(FragileTask(n)).unwrap();
+        }
+        let results: Vec<_> = q.iter_tagged().collect();
+        assert_eq!(8, results.len());
+        for (id, result) in results {
+            match result {
+                Ok(output) => assert_eq!(id.number(), output),
+                Err(e) => assert_eq!(TaskPanic { task: id, message: format!("odd task {}", id.number()) }, e),
+            }
+        }
+
+        // the pool is still full size: the next tasks run as concurrently as ever, and the
+        // threads that panicked aren't kept around
+        assert_eq!(n_threads as usize, q.workers());
+        let start = Instant::now();
+        for n in 0..2 * n_threads {
+            q.enqueue(FragileTask(2 * n)).unwrap();
+        }
+        assert_eq!(vec![0, 2, 4, 6], q.iter().collect::<Vec<_>>());
+        assert!(start.elapsed() < DELAY * 3 / 2, "took {:?}", start.elapsed());
+
+        // untagged receivers skip tasks that panicked
+        for n in 1..4 {
+            q.enqueue(FragileTask(n)).unwrap();
+        }
+        assert_eq!(2, q.recv());
+        assert_eq!(None, q.recv_pending());
+        assert_eq!(n_threads as usize, q.workers());
+        q.shutdown();
+        assert_eq!(0, q.workers());
+    }
+
+    #[test]
+    // Test that a queue with a task capacity holds back producers instead of buffering
+    fn bounded() {
+        let started = sync::Arc::new(AtomicUsize::new(0));
+        let options = QueueOptions { task_capacity: Some(2), ..QueueOptions::default() };
+        let mut q = WorkQueue::<GatedTask>::with_options(1, options).unwrap();
+
+        // one task running, two waiting: no room for a fourth
+        let (task, open0) = gated_task(0, &started);
+        q.enqueue(task).unwrap();
+        wait_for_starts(&started, 1);
+        let (task, open1) = gated_task(1, &started);
+        q.enqueue(task).unwrap();
+        q.enqueue(gated_task(2, &started).0).unwrap();
+        assert_eq!(2, q.queued());
+        assert!(matches!(q.try_enqueue(gated_task(3, &started).0), Err(mpsc::TrySendError::Full(_))));
+
+        // enqueue waits for the worker to take a task, which it does once task 0 is let go
+        let letting_go = thread::spawn(move || open0.send(()).unwrap());
+        q.enqueue(gated_task(3, &started).0).unwrap();
+        assert_eq!(2, q.queued());
+        letting_go.join().unwrap();
+        drop(open1);
+        assert_eq!(vec![0, 1, 2, 3], q.iter().collect::<Vec<_>>());
+        assert_eq!(0, q.queued());
+
+        // outputs aren't limited, so one thread can keep enqueueing without receiving
+        for n in 0..20 {
+            q.enqueue(gated_task(n, &started).0).unwrap();
+        }
+        assert!(q.wait_idle());
+        assert_eq!((0..20).collect::<Vec<_>>(), q.iter().collect::<Vec<_>>());
+        q.shutdown();
+        assert!(matches!(q.try_enqueue(gated_task(4, &started).0), Err(mpsc::TrySendError::Disconnected(_))));
+
+        // limits that could never be met
+        for (task_capacity, output_capacity) in [(Some(0), None), (None, Some(0)), (Some(0), Some(2))] {
+            let options = QueueOptions { task_capacity, output_capacity, ..QueueOptions::default() };
+            assert_eq!(Some(QueueOptionsError::ZeroCapacity), WorkQueue::<GatedTask>::with_options(1, options).err());
+        }
+    }
+
+    #[test]
+    // Test that a queue with both capacities keeps tasks and outputs within them, for a thread
+    // that only receives when the queue is full
+    fn bounded_both() {
+        for ordered in [false, true] {
+            let started = sync::Arc::new(AtomicUsize::new(0));
+            let options = QueueOptions { ordered, task_capacity: Some(2), output_capacity: Some(3) };
+            let mut q = WorkQueue::<GatedTask>::with_options(2, options).unwrap();
+
+            let mut outputs = Vec::new();
+            for n in 0..30 {
+                let mut task = gated_task(n, &started).0;
+                loop {
+                    match q.try_enqueue(task) {
+                        Ok(_) => break,
+                        Err(mpsc::TrySendError::Full(t)) => {
+                            task = t;
+                            outputs.push(q.recv());
+                        }
+                        Err(mpsc::TrySendError::Disconnected(_)) => panic!("the queue hasn't been shut down"),
+                    }
+                    assert!(q.queued() <= 2);
+                    // started but not received: running, or holding an output
+                    assert!(started.load(Ordering::SeqCst) <= outputs.len() + 3);
+                }
+            }
+            outputs.extend(q.iter());
+            if !ordered {
+                outputs.sort();
+            }
+            assert_eq!((0..30).collect::<Vec<_>>(), outputs);
+        }
+    }
+
+    #[test]
+    // Test that a queue with an output capacity only runs tasks while there's room for their outputs
+    fn bounded_outputs() {
+        for ordered in [false, true] {
+            let started = sync::Arc::new(AtomicUsize::new(0));
+            let options = QueueOptions { ordered, output_capacity: Some(3), ..QueueOptions::default() };
+            let mut q = WorkQueue::<GatedTask>::with_options(2, options).unwrap();
+
+            // one thread enqueues plenty without receiving; task 0 is slow to finish
+            let (task, open0) = gated_task(0, &started);
+            q.enqueue(task).unwrap();
+            for n in 1..30 {
+                q.enqueue(gated_task(n, &started).0).unwrap();
+            }
+            // tasks 1 and 2 finish first, and nothing after them may run even once task 0 is done
+            wait_for_starts(&started, 3);
+            open0.send(()).unwrap();
+            assert!(!q.wait_idle());
+            assert_eq!(3, started.load(Ordering::SeqCst));
+
+            // each output received lets one more task run
+            let first = q.recv();
+            assert!(!q.wait_idle());
+            assert_eq!(4, started.load(Ordering::SeqCst));
+            let mut outputs: Vec<u64> = std::iter::once(first).chain(q.iter()).collect();
+            if ordered {
+                assert_eq!((0..30).collect::<Vec<_>>(), outputs);
+            } else {
+                outputs.sort();
+                assert_eq!((0..30).collect::<Vec<_>>(), outputs);
+            }
+
+            // shutting down discards the tasks waiting for room, and keeps the outputs held
+            for n in 0..10 {
+                q.enqueue(gated_task(n, &started).0).unwrap();
+            }
+            assert!(!q.wait_idle());
+            q.shutdown();
+            assert_eq!(0, q.pending());
+            assert_eq!(3, q.iter().count());
+        }
+    }
 }
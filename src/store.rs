@@ -0,0 +1,417 @@
+use crate::block::{Block, Hash, ValidationError};
+use crate::chain::{Blockchain, ChainParams};
+use crate::clock::{Clock, SystemClock};
+use crate::encoding::DecodeError;
+use crate::json::JsonError;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// An append-only store of a chain's blocks on disk. Blocks are written in their binary
+// encoding to numbered segment files, each record as:
+//   length (u32, big-endian) | checksum (first 4 bytes of the SHA-256 of length and block) | block
+// Segment 0 starts with a record of the same shape holding the chain's parameters as JSON,
+// so a store can't be reopened under different rules.
+// A crash can leave a torn record at the end of the last segment; opening the store cuts
+// it off, but only if no valid record follows it, and only once every block has been
+// validated again, so a store always holds a valid chain and damage is never cut away.
+
+// bytes before each encoded block in a record
+const RECORD_HEADER_LEN: usize = 8;
+
+pub const DEFAULT_SEGMENT_SIZE: u64 = 64 << 20;
+
+// When appended blocks are flushed to the disk itself (with fsync), rather than left to the OS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    #[default]
+    Always,        // after every block: nothing acknowledged is lost in a crash
+    EveryN(u32),   // after every N blocks: up to N - 1 of the latest blocks can be lost
+    Never,         // only on BlockStore::sync
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreOptions {
+    pub segment_size: u64, // start a new segment file rather than grow one past this size
+    pub sync: SyncPolicy,
+}
+
+impl Default for StoreOptions {
+    fn default() -> StoreOptions {
+        StoreOptions { segment_size: DEFAULT_SEGMENT_SIZE, sync: SyncPolicy::default() }
+    }
+}
+
+// Where a block's record is: segment file number and byte offset in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub segment: u32,
+    pub offset: u64,
+    pub len: u32, // of the whole record
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(io::Error),
+    AlreadyExists,                                              // create on a directory that already holds segments
+    Empty,                                                      // open on a store without a genesis block
+    Corrupt { segment: u32, offset: u64 },                      // a damaged record that isn't a torn write
+    MissingSegment(u32),                                        // a segment file is gone, but later ones aren't
+    BadParams(JsonError),                                       // the parameters record can't be read
+    ParamsMismatch { stored: ChainParams },                     // open with other parameters than create
+    Decode { segment: u32, offset: u64, error: DecodeError },
+    Invalid { height: u64, error: ValidationError },            // a block that doesn't extend the chain
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "{}", e),
+            StoreError::AlreadyExists => write!(f, "a block store already exists there"),
+            StoreError::Empty => write!(f, "the block store has no genesis block"),
+            StoreError::Corrupt { segment, offset } => {
+                write!(f, "corrupt record in segment {} at offset {}", segment, offset)
+            }
+            StoreError::MissingSegment(segment) => write!(f, "segment {} is missing, but later ones exist", segment),
+            StoreError::BadParams(error) => write!(f, "unreadable chain parameters: {}", error),
+            StoreError::ParamsMismatch { stored } => {
+                write!(f, "the block store was created with different chain parameters: {:?}", stored)
+            }
+            StoreError::Decode { segment, offset, error } => {
+                write!(f, "undecodable block in segment {} at offset {}: {}", segment, offset, error)
+            }
+            StoreError::Invalid { height, error } => write!(f, "block {} is invalid: {}", height, error),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> StoreError {
+        StoreError::Io(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockStore {
+    dir: PathBuf,
+    options: StoreOptions,
+    chain: Blockchain,
+    locations: Vec<Location>,             // height -> record
+    by_hash: HashMap<Hash, Location>,     // block hash -> record
+    file: File,                           // the last segment, which appends go to
+    segment: u32,
+    segment_len: u64,
+    unsynced: u32,                        // blocks appended since the last fsync
+    recovered: u64,                       // bytes of torn record cut off when opening
+}
+
+fn segment_path(dir: &Path, segment: u32) -> PathBuf {
+    dir.join(format!("blocks-{:05}.dat", segment))
+}
+
+fn checksum(len: &[u8], block: &[u8]) -> [u8; 4] {
+    // covers the length too, so a damaged length can't pass for a torn write
+    Sha256::new().chain_update(len).chain_update(block).finalize()[..4].try_into().unwrap()
+}
+
+pub(crate) fn encode_record(block: &Block) -> Vec<u8> {
+    frame_record(&block.to_bytes())
+}
+
+fn frame_record(bytes: &[u8]) -> Vec<u8> {
+    let len = (bytes.len() as u32).to_be_bytes();
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + bytes.len());
+    record.extend_from_slice(&len);
+    record.extend_from_slice(&checksum(&len, bytes));
+    record.extend_from_slice(bytes);
+    record
+}
+
+fn record_len(header: &[u8]) -> usize {
+    // the length of the encoded block, from a record header
+    u32::from_be_bytes(header[..4].try_into().unwrap()) as usize
+}
+
+// The record at the start of `bytes`: Ok(Some(block bytes)), Ok(None) if it runs past the
+// end (a torn write), or Err(()) if its checksum doesn't match.
+fn parse_record(bytes: &[u8]) -> Result<Option<&[u8]>, ()> {
+    if bytes.len() < RECORD_HEADER_LEN {
+        return Ok(None);
+    }
+    let Some(block) = bytes[RECORD_HEADER_LEN..].get(..record_len(bytes)) else {
+        return Ok(None);
+    };
+    if checksum(&bytes[..4], block) != bytes[4..RECORD_HEADER_LEN] {
+        return Err(());
+    }
+    Ok(Some(block))
+}
+
+fn holds_record(bytes: &[u8]) -> bool {
+    // is there a valid record at any offset in `bytes`?
+    (0..bytes.len()).any(|offset| matches!(parse_record(&bytes[offset..]), Ok(Some(_))))
+}
+
+fn segment_numbers(dir: &Path) -> io::Result<Vec<u32>> {
+    // the numbers of the segment files in `dir`, in order; a missing directory is an error
+    let mut numbers = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let number = name.to_str().and_then(|n| n.strip_prefix("blocks-")?.strip_suffix(".dat")?.parse().ok());
+        // only names segment_path would give
+        if let Some(number) = number.filter(|&n| segment_path(dir, n).file_name() == Some(&name)) {
+            numbers.push(number);
+        }
+    }
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+fn segment_count(dir: &Path) -> Result<u32, StoreError> {
+    // segment files are numbered from 0 with no gaps; a gap means blocks have been lost
+    let numbers = segment_numbers(dir)?;
+    match (0..).zip(&numbers).find(|&(expected, &n)| n != expected) {
+        Some((missing, _)) => Err(StoreError::MissingSegment(missing)),
+        None => Ok(numbers.len() as u32),
+    }
+}
+
+fn create_segment(dir: &Path, segment: u32) -> Result<File, StoreError> {
+    // and make its directory entry durable, so the file survives a crash along with its contents
+    let file = OpenOptions::new().create_new(true).append(true).open(segment_path(dir, segment))?;
+    sync_dir(dir)?;
+    Ok(file)
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    // directories can't be opened, nor need to be synced, elsewhere
+    Ok(())
+}
+
+impl BlockStore {
+    pub fn create(dir: &Path, params: ChainParams, genesis: Block, options: StoreOptions) -> Result<BlockStore, StoreError> {
+        BlockStore::create_with_clock(dir, params, genesis, options, Arc::new(SystemClock))
+    }
+
+    pub fn create_with_clock(
+        dir: &Path,
+        params: ChainParams,
+        genesis: Block,
+        options: StoreOptions,
+        clock: Arc<dyn Clock>,
+    ) -> Result<BlockStore, StoreError> {
+        // start a new store in `dir` (created if needed) holding a chain from `genesis`, which
+        // checks timestamps against `clock` as Blockchain::with_clock does
+        fs::create_dir_all(dir)?;
+        if !segment_numbers(dir)?.is_empty() {
+            return Err(StoreError::AlreadyExists);
+        }
+        let chain = Blockchain::with_clock(params, genesis.clone(), clock).map_err(|error| StoreError::Invalid { height: 0, error })?;
+        let file = create_segment(dir, 0)?;
+        let mut store = BlockStore {
+            dir: dir.to_path_buf(),
+            options,
+            chain,
+            locations: Vec::new(),
+            by_hash: HashMap::new(),
+            file,
+            segment: 0,
+            segment_len: 0,
+            unsynced: 0,
+            recovered: 0,
+        };
+        store.write_record(&frame_record(params.to_json().as_bytes()))?;
+        store.write(&genesis)?;
+        store.sync_if_due()?;
+        Ok(store)
+    }
+
+    pub fn open(dir: &Path, params: ChainParams, options: StoreOptions) -> Result<BlockStore, StoreError> {
+        BlockStore::open_with_clock(dir, params, options, Arc::new(SystemClock))
+    }
+
+    pub fn open_with_clock(
+        dir: &Path,
+        params: ChainParams,
+        options: StoreOptions,
+        clock: Arc<dyn Clock>,
+    ) -> Result<BlockStore, StoreError> {
+        // Reopen a store, cutting off a torn record at its end and validating every block.
+        // `params` must be the ones it was created with.
+        let count = segment_count(dir)?;
+        let mut stored_params = None;
+        let mut blocks = Vec::new();
+        let mut locations = Vec::new();
+        let mut recovered = 0;
+        let mut segment_len = 0;
+        for segment in 0..count {
+            let bytes = fs::read(segment_path(dir, segment))?;
+            let last = segment + 1 == count;
+            let mut offset = 0;
+            while offset < bytes.len() {
+                let corrupt = StoreError::Corrupt { segment, offset: offset as u64 };
+                let record = match parse_record(&bytes[offset..]) {
+                    Ok(Some(record)) => record,
+                    // runs past the end or fails its checksum, with nothing valid after it: a torn write
+                    _ if last && !holds_record(&bytes[offset + 1..]) => break,
+                    _ => return Err(corrupt),
+                };
+                let len = RECORD_HEADER_LEN + record.len();
+                if segment == 0 && offset == 0 {
+                    stored_params = Some(record.to_vec());
+                    offset += len;
+                    continue;
+                }
+                let block = Block::from_bytes(record)
+                    .map_err(|error| StoreError::Decode { segment, offset: offset as u64, error })?;
+                locations.push(Location { segment, offset: offset as u64, len: len as u32 });
+                blocks.push(block);
+                offset += len;
+            }
+            recovered = (bytes.len() - offset) as u64;
+            segment_len = offset as u64;
+        }
+
+        let stored_params = stored_params.ok_or(StoreError::Empty)?;
+        let stored = std::str::from_utf8(&stored_params)
+            .map_err(|e| StoreError::BadParams(JsonError::Syntax(e.to_string())))
+            .and_then(|json| ChainParams::from_json(json).map_err(StoreError::BadParams))?;
+        if stored != params {
+            return Err(StoreError::ParamsMismatch { stored });
+        }
+        let mut blocks = blocks.into_iter();
+        let genesis = blocks.next().ok_or(StoreError::Empty)?;
+        let mut chain = Blockchain::with_clock(params, genesis, clock).map_err(|error| StoreError::Invalid { height: 0, error })?;
+        for (height, block) in (1..).zip(blocks) {
+            chain.push(block).map_err(|error| StoreError::Invalid { height, error })?;
+        }
+        let by_hash = chain.iter().zip(&locations).map(|(b, &l)| (b.hash(), l)).collect();
+        let segment = count - 1;
+        let file = OpenOptions::new().append(true).open(segment_path(dir, segment))?;
+        if recovered > 0 {
+            // only now that the rest is known to be good
+            file.set_len(segment_len)?;
+            file.sync_all()?;
+        }
+        Ok(BlockStore {
+            dir: dir.to_path_buf(),
+            options,
+            chain,
+            locations,
+            by_hash,
+            file,
+            segment,
+            segment_len,
+            unsynced: 0,
+            recovered,
+        })
+    }
+
+    fn write(&mut self, block: &Block) -> Result<(), StoreError> {
+        // append `block`'s record and index it
+        let location = self.write_record(&encode_record(block))?;
+        self.locations.push(location);
+        self.by_hash.insert(block.hash(), location);
+        self.unsynced += 1;
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[u8]) -> Result<Location, StoreError> {
+        // append a record, moving on to a new segment if this one is full
+        if self.segment_len > 0 && self.segment_len + record.len() as u64 > self.options.segment_size {
+            self.file.sync_all()?;
+            // moved on to only once the file exists, so a failure leaves appends going to this one
+            self.file = create_segment(&self.dir, self.segment + 1)?;
+            self.segment += 1;
+            self.segment_len = 0;
+        }
+        if let Err(e) = self.file.write_all(record) {
+            // don't leave part of a record behind for the next one to follow
+            let _ = self.file.set_len(self.segment_len);
+            return Err(e.into());
+        }
+        let location = Location { segment: self.segment, offset: self.segment_len, len: record.len() as u32 };
+        self.segment_len += record.len() as u64;
+        Ok(location)
+    }
+
+    fn sync_if_due(&mut self) -> Result<(), StoreError> {
+        // fsync as the SyncPolicy says, once the blocks written are in the chain
+        let due = match self.options.sync {
+            SyncPolicy::Always => true,
+            SyncPolicy::EveryN(n) => self.unsynced >= n,
+            SyncPolicy::Never => false,
+        };
+        if due {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    pub fn append(&mut self, block: Block) -> Result<(), StoreError> {
+        // store `block` if it correctly extends the chain
+        self.chain
+            .validate_next(&block)
+            .map_err(|error| StoreError::Invalid { height: self.chain.height() + 1, error })?;
+        self.write(&block)?;
+        self.chain.push(block).expect("block was validated before it was written");
+        // only now, so that if it fails the block is both on disk and in the chain, as it will be
+        // when the store is reopened; the error only says it may not have reached the disk itself
+        self.sync_if_due()
+    }
+
+    pub fn mine_next(&mut self, data: String, workers: usize) -> Result<&Block, StoreError> {
+        let block = self.chain.mine_block(data, workers);
+        self.append(block)?;
+        Ok(self.chain.tip())
+    }
+
+    pub fn sync(&mut self) -> Result<(), StoreError> {
+        self.file.sync_all()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    pub fn chain(&self) -> &Blockchain {
+        &self.chain
+    }
+
+    pub fn recovered_bytes(&self) -> u64 {
+        self.recovered
+    }
+
+    pub fn segments(&self) -> u32 {
+        self.segment + 1
+    }
+
+    pub fn location(&self, height: u64) -> Option<Location> {
+        self.locations.get(usize::try_from(height).ok()?).copied()
+    }
+
+    pub fn location_of_hash(&self, hash: &Hash) -> Option<Location> {
+        self.by_hash.get(hash).copied()
+    }
+
+    pub fn read(&self, location: Location) -> Result<Block, StoreError> {
+        // read a block back from the disk rather than from memory
+        let mut file = File::open(segment_path(&self.dir, location.segment))?;
+        file.seek(SeekFrom::Start(location.offset))?;
+        let mut bytes = vec![0; location.len as usize];
+        file.read_exact(&mut bytes)?;
+        let corrupt = || StoreError::Corrupt { segment: location.segment, offset: location.offset };
+        let record = parse_record(&bytes).map_err(|_| corrupt())?.ok_or_else(corrupt)?;
+        Block::from_bytes(record).map_err(|error| StoreError::Decode { segment: location.segment, offset: location.offset, error })
+    }
+}
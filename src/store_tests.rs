@@ -0,0 +1,227 @@
+#[cfg(test)]
+mod store_tests {
+    use crate::block::{Block, ValidationError};
+    use crate::chain::{Blockchain, ChainParams, Retarget};
+    use crate::clock::{Clock, ManualClock, SystemClock};
+    use crate::store::{encode_record, BlockStore, StoreError, StoreOptions, SyncPolicy};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("a3-store-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn segment(dir: &Path, n: u32) -> PathBuf {
+        dir.join(format!("blocks-{:05}.dat", n))
+    }
+
+    fn sample_store(dir: &Path, blocks: usize, options: StoreOptions) -> BlockStore {
+        let genesis = Blockchain::mine_genesis(8, 1).genesis().clone();
+        let mut store = BlockStore::create(dir, ChainParams::default(), genesis, options).unwrap();
+        for i in 1..=blocks {
+            store.mine_next(format!("block {}", i), 1).unwrap();
+        }
+        store
+    }
+
+    // Test that a store reopens with the same chain, and finds blocks by height and hash
+    #[test]
+    fn reopen() {
+        let dir = temp_dir("reopen");
+        let options = StoreOptions { sync: SyncPolicy::EveryN(2), ..StoreOptions::default() };
+        let mut store = sample_store(&dir, 3, options);
+        store.sync().unwrap();
+        let chain = store.chain().clone();
+        assert!(matches!(
+            BlockStore::create(&dir, ChainParams::default(), chain.genesis().clone(), options),
+            Err(StoreError::AlreadyExists)
+        ));
+        drop(store);
+
+        let mut store = BlockStore::open(&dir, ChainParams::default(), options).unwrap();
+        assert_eq!(chain.blocks(), store.chain().blocks());
+        assert_eq!(0, store.recovered_bytes());
+        for block in chain.iter() {
+            let location = store.location(block.generation()).unwrap();
+            assert_eq!(Some(location), store.location_of_hash(&block.hash()));
+            assert_eq!(*block, store.read(location).unwrap());
+        }
+        assert_eq!(None, store.location(4));
+
+        // appends continue where the store left off, and are checked first
+        store.mine_next(String::from("after reopening"), 1).unwrap();
+        let stale = Block::next(chain.get(1).unwrap(), String::from("stale"));
+        assert!(matches!(
+            store.append(stale),
+            Err(StoreError::Invalid { height: 5, error: ValidationError::BadParentLink { .. } })
+        ));
+        drop(store);
+        let store = BlockStore::open(&dir, ChainParams::default(), StoreOptions::default()).unwrap();
+        assert_eq!(5, store.chain().len());
+        assert!(matches!(
+            BlockStore::open(&temp_dir("missing"), ChainParams::default(), options),
+            Err(StoreError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound
+        ));
+        let empty = temp_dir("empty");
+        fs::create_dir_all(&empty).unwrap();
+        assert!(matches!(BlockStore::open(&empty, ChainParams::default(), options), Err(StoreError::Empty)));
+        fs::remove_dir_all(&empty).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Test that a store keeps to the parameters and clock it was created with
+    #[test]
+    fn params_and_clock() {
+        let dir = temp_dir("params");
+        let options = StoreOptions::default();
+        // ahead of the system clock by more than a timestamp may be
+        let clock = Arc::new(ManualClock::new(SystemClock.now() + 24 * 60 * 60));
+        let params = ChainParams { timestamps: true, ..ChainParams::default() };
+        let genesis = Blockchain::mine_genesis_with_clock(params, 8, 1, clock.clone()).genesis().clone();
+        let mut store = BlockStore::create_with_clock(&dir, params, genesis, options, clock.clone()).unwrap();
+        clock.advance(60);
+        store.mine_next(String::from("from the future"), 1).unwrap();
+        let chain = store.chain().clone();
+        drop(store);
+
+        assert!(matches!(
+            BlockStore::open(&dir, params, options),
+            Err(StoreError::Invalid { height: 0, error: ValidationError::TimestampTooFarAhead { .. } })
+        ));
+        let store = BlockStore::open_with_clock(&dir, params, options, clock.clone()).unwrap();
+        assert_eq!(chain.blocks(), store.chain().blocks());
+        assert_eq!(&params, store.chain().params());
+        drop(store);
+
+        // other rules than the blocks were stored under
+        let retarget = Retarget::FixedWindow { interval: 4, spacing: 600 };
+        for other in [ChainParams { retarget, ..params }, ChainParams::default()] {
+            assert!(matches!(
+                BlockStore::open_with_clock(&dir, other, options, clock.clone()),
+                Err(StoreError::ParamsMismatch { stored }) if stored == params
+            ));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Test that blocks are spread over segment files of limited size
+    #[test]
+    fn segments() {
+        let dir = temp_dir("segments");
+        let options = StoreOptions { segment_size: 250, sync: SyncPolicy::Never };
+        let mut store = sample_store(&dir, 5, options);
+        store.sync().unwrap();
+        assert!(store.segments() > 1);
+        for n in 0..store.segments() {
+            assert!(fs::metadata(segment(&dir, n)).unwrap().len() <= 250);
+        }
+        let last = store.location(5).unwrap();
+        assert_eq!(store.segments() - 1, last.segment);
+        let chain = store.chain().clone();
+        drop(store);
+
+        let mut store = BlockStore::open(&dir, ChainParams::default(), options).unwrap();
+        assert_eq!(chain.blocks(), store.chain().blocks());
+        assert_eq!(Some(last), store.location(5));
+
+        // a new segment that can't be created fails the append, and the next one tries again
+        let next = segment(&dir, store.segments());
+        fs::create_dir(&next).unwrap();
+        let segments = store.segments();
+        while store.segments() == segments {
+            match store.mine_next(String::from("blocked"), 1) {
+                Ok(block) => assert!(block.generation() < 20),
+                Err(StoreError::Io(_)) => break,
+                Err(e) => panic!("expected an I/O error, got {:?}", e),
+            }
+        }
+        assert_eq!(segments, store.segments());
+        let height = store.chain().height();
+        fs::remove_dir(&next).unwrap();
+        store.mine_next(String::from("unblocked"), 1).unwrap();
+        assert_eq!(segments, store.location(height + 1).unwrap().segment);
+        let chain = store.chain().clone();
+        drop(store);
+        let store = BlockStore::open(&dir, ChainParams::default(), options).unwrap();
+        assert_eq!(chain.blocks(), store.chain().blocks());
+        drop(store);
+
+        // a gap in the segment numbers loses the blocks after it, so it's an error
+        fs::rename(segment(&dir, 1), dir.join("moved")).unwrap();
+        assert!(matches!(BlockStore::open(&dir, ChainParams::default(), options), Err(StoreError::MissingSegment(1))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Test that a torn last record is cut off, but damage elsewhere is an error
+    #[test]
+    fn recovery() {
+        let dir = temp_dir("recovery");
+        let store = sample_store(&dir, 3, StoreOptions::default());
+        let chain = store.chain().clone();
+        let b2 = store.location(2).unwrap();
+        let tip = store.location(3).unwrap();
+        drop(store);
+        let path = segment(&dir, 0);
+        let intact = fs::read(&path).unwrap();
+
+        // the last record only partly written, or its header alone
+        for keep in [tip.len as u64 - 1, 5] {
+            fs::write(&path, &intact[..(tip.offset + keep) as usize]).unwrap();
+            let mut store = BlockStore::open(&dir, ChainParams::default(), StoreOptions::default()).unwrap();
+            assert_eq!(keep, store.recovered_bytes());
+            assert_eq!(&chain.blocks()[..3], store.chain().blocks());
+            assert_eq!(tip.offset, fs::metadata(&path).unwrap().len());
+            store.append(chain.tip().clone()).unwrap();
+            assert_eq!(intact, fs::read(&path).unwrap());
+        }
+
+        // the last record complete but scrambled
+        let mut scrambled = intact.clone();
+        *scrambled.last_mut().unwrap() ^= 1;
+        fs::write(&path, &scrambled).unwrap();
+        let store = BlockStore::open(&dir, ChainParams::default(), StoreOptions::default()).unwrap();
+        assert_eq!(tip.len as u64, store.recovered_bytes());
+        assert_eq!(3, store.chain().len());
+        drop(store);
+
+        // a scrambled record with more after it wasn't torn by a crash
+        let b1 = chain.get(1).unwrap();
+        let mut scrambled = intact.clone();
+        scrambled[tip.offset as usize - 1] ^= 1;
+        fs::write(&path, &scrambled).unwrap();
+        assert!(matches!(
+            BlockStore::open(&dir, ChainParams::default(), StoreOptions::default()),
+            Err(StoreError::Corrupt { segment: 0, offset }) if offset == b2.offset
+        ));
+
+        // nor was a record whose length was damaged, whether it now runs past the end or not,
+        // and the valid records after it are kept
+        for (byte, bit) in [(0, 0x01), (3, 0x01), (3, 0x80)] {
+            let mut scrambled = intact.clone();
+            scrambled[b2.offset as usize + byte] ^= bit;
+            fs::write(&path, &scrambled).unwrap();
+            assert!(matches!(
+                BlockStore::open(&dir, ChainParams::default(), StoreOptions::default()),
+                Err(StoreError::Corrupt { segment: 0, offset }) if offset == b2.offset
+            ));
+            assert_eq!(scrambled, fs::read(&path).unwrap());
+        }
+
+        // well-formed records must still extend the chain, and nothing is cut off until they do
+        fs::write(&path, &intact[..tip.offset as usize]).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&encode_record(b1)).unwrap();
+        file.write_all(&intact[tip.offset as usize..][..5]).unwrap();
+        let len = fs::metadata(&path).unwrap().len();
+        assert!(matches!(
+            BlockStore::open(&dir, ChainParams::default(), StoreOptions::default()),
+            Err(StoreError::Invalid { height: 3, .. })
+        ));
+        assert_eq!(len, fs::metadata(&path).unwrap().len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}